@@ -1,3 +1,94 @@
+use inkwell::{
+    context::Context,
+    module::{Linkage, Module},
+    passes::PassBuilderOptions,
+    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine},
+    OptimizationLevel,
+};
+use std::{env, path::PathBuf, process::Command};
+
+/// The RTS functions used by generated code. Everything else is removed.
+const RTS_API: &[&str] = &[
+    "noop",
+    "new_app",
+    "new_partial",
+    "apply_partial",
+    "copy",
+    "free_args",
+    "free_term",
+    "todo",
+];
+
 fn main() {
     lalrpop::process_root().unwrap();
+    build_rts();
+}
+
+/// Compiles the RTS to bitcode and strips it down to the API, leaving
+/// `$OUT_DIR/rts.bc` to be linked into every program.
+fn build_rts() {
+    println!("cargo:rerun-if-changed=../rts/src");
+    println!("cargo:rerun-if-changed=../rts/Cargo.toml");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target_dir = out_dir.join("rts");
+
+    // A separate target directory avoids waiting on the lock held by the
+    // cargo running this script, and the encoded flags would take precedence
+    // over `RUSTFLAGS`.
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args(["build", "--release", "--manifest-path", "../rts/Cargo.toml"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env("RUSTFLAGS", "--emit=llvm-bc")
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build the RTS");
+
+    let bitcode = target_dir.join("release/deps/rts.bc");
+    let context = Context::create();
+    let module = Module::parse_bitcode_from_path(&bitcode, &context).unwrap();
+
+    // Like `opt --internalize-public-api-list=... --passes=internalize,globaldce`
+    internalize(&module, |name| RTS_API.contains(&name));
+    Target::initialize_native(&InitializationConfig::default()).unwrap();
+    let triple = TargetMachine::get_default_triple();
+    let machine = Target::from_triple(&triple)
+        .unwrap()
+        .create_target_machine(
+            &triple,
+            "",
+            "",
+            OptimizationLevel::None,
+            RelocMode::Default,
+            CodeModel::Default,
+        )
+        .unwrap();
+    module
+        .run_passes("globaldce", &machine, PassBuilderOptions::create())
+        .unwrap();
+
+    // The API only needs to be visible to the generated code in the same
+    // module, so it is internalized too.
+    internalize(&module, |_| false);
+
+    assert!(module.write_bitcode_to_path(&out_dir.join("rts.bc")));
+}
+
+/// Gives every definition internal linkage, except those for which `keep`
+/// returns true and LLVM's own globals like `llvm.used`.
+fn internalize(module: &Module, keep: impl Fn(&str) -> bool) {
+    for function in module.get_functions() {
+        let name = function.get_name().to_string_lossy();
+        if function.count_basic_blocks() > 0 && !keep(&name) {
+            function.set_linkage(Linkage::Internal);
+        }
+    }
+    for global in module.get_globals() {
+        let name = global.get_name().to_string_lossy();
+        if global.get_initializer().is_some() && !keep(&name) && !name.starts_with("llvm.") {
+            global.set_linkage(Linkage::Internal);
+        }
+    }
 }
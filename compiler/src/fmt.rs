@@ -0,0 +1,101 @@
+use crate::{
+    lir,
+    report::{self, Level},
+};
+
+/// Rewrites LIR files in the canonical form printed by `lir::print`, keeping
+/// their comments, or with `check`, lists the files that aren't in it without
+/// changing them.
+///
+/// Every file is formatted even if an earlier one fails, and the exit code is
+/// that of the first failure.
+pub fn fmt(files: Vec<String>, check: bool) -> i32 {
+    let mut code = 0;
+    for file in files {
+        if let Err(error) = fmt_file(&file, check) {
+            code = if code == 0 { error } else { code };
+        }
+    }
+    code
+}
+
+fn fmt_file(file: &str, check: bool) -> Result<(), i32> {
+    if file.ends_with(".mir") {
+        eprintln!("Cannot format {}, only LIR can be formatted", file);
+        return Err(1);
+    }
+
+    let input = match std::fs::read_to_string(file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("Failed to read file: {}", e);
+            return Err(1);
+        }
+    };
+    let (globals, trivia) = match lir::parse::parse_with_trivia(&input) {
+        Ok(parsed) => parsed,
+        Err(errors) => {
+            report::print_parse_errors(file, &input, errors);
+            return Err(2);
+        }
+    };
+
+    // Formatting would drop all but the last definition of a global.
+    let (program, diagnostics) = lir::parse::collect(globals, Level::Error);
+    if report::has_errors(&diagnostics) {
+        report::print_diagnostics(file, &input, &diagnostics);
+        return Err(2);
+    }
+
+    let output = lir::print::format(&program, &input, &trivia);
+    if output == input {
+        return Ok(());
+    }
+    if check {
+        println!("{} is not formatted", file);
+        return Err(1);
+    }
+    if let Err(e) = std::fs::write(file, output) {
+        eprintln!("Failed to write file: {}", e);
+        return Err(6);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt_file() {
+        let file = std::env::temp_dir().join(format!("yoke-fmt-{}.lir", std::process::id()));
+        let file = file.to_str().unwrap();
+        std::fs::write(
+            file,
+            "main = 0 { load_global True return_symbol True } -- main\nTrue = 0",
+        )
+        .unwrap();
+
+        assert_eq!(fmt_file(file, true), Err(1));
+        assert_eq!(fmt_file(file, false), Ok(()));
+        let output = std::fs::read_to_string(file).unwrap();
+        assert_eq!(
+            output,
+            "True = 0\n\nmain = 0 {\n  load_global True\n  return_symbol True\n} -- main\n"
+        );
+        assert_eq!(fmt_file(file, true), Ok(()));
+        assert_eq!(std::fs::read_to_string(file).unwrap(), output);
+
+        std::fs::write(file, "main = 0 {").unwrap();
+        assert_eq!(fmt_file(file, false), Err(2));
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "main = 0 {");
+
+        std::fs::write(file, "True = 0\nTrue = 1\n").unwrap();
+        assert_eq!(fmt_file(file, false), Err(2));
+        assert_eq!(
+            std::fs::read_to_string(file).unwrap(),
+            "True = 0\nTrue = 1\n"
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+}
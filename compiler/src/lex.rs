@@ -0,0 +1,182 @@
+use crate::report::Span;
+use std::fmt::{self, Display};
+
+/// A comment, which is `--` or `#` to the end of the line, or a block between
+/// `{-` and `-}`, which can be nested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    /// Whether there is code before the comment on the same line.
+    pub trailing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    InvalidToken { location: usize },
+    UnterminatedComment { span: Span },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidToken { location } => *location..*location,
+            LexError::UnterminatedComment { span } => span.clone(),
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::InvalidToken { .. } => write!(f, "unrecognized token"),
+            LexError::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
+        }
+    }
+}
+
+/// Walks source text for a lexer, skipping the whitespace and comments
+/// between tokens and keeping the comments.
+pub struct Scanner<'input> {
+    input: &'input str,
+    position: usize,
+    /// Where the last token ended.
+    last: Option<usize>,
+    pub comments: Vec<Comment>,
+}
+
+impl<'input> Scanner<'input> {
+    pub fn new(input: &'input str) -> Self {
+        Self {
+            input,
+            position: 0,
+            last: None,
+            comments: vec![],
+        }
+    }
+
+    /// Skips to the start of the next token, returning its first character,
+    /// or `None` at the end of the input.
+    pub fn next_token(&mut self) -> Result<Option<char>, LexError> {
+        loop {
+            let rest = &self.input[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            let start = self.position;
+            if trimmed.starts_with("--") || trimmed.starts_with('#') {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("{-") {
+                self.position = self.block_comment(start)?;
+            } else {
+                return Ok(trimmed.chars().next());
+            }
+
+            let trailing = self
+                .last
+                .is_some_and(|end| !self.input[end..start].contains('\n'));
+            self.comments.push(Comment {
+                span: start..self.position,
+                trailing,
+            });
+        }
+    }
+
+    /// Finds the end of the block comment starting at `start`.
+    fn block_comment(&self, start: usize) -> Result<usize, LexError> {
+        let mut depth = 0;
+        let mut position = start;
+        while position < self.input.len() {
+            let rest = &self.input[position..];
+            if rest.starts_with("{-") {
+                depth += 1;
+                position += 2;
+            } else if rest.starts_with("-}") {
+                depth -= 1;
+                position += 2;
+                if depth == 0 {
+                    return Ok(position);
+                }
+            } else {
+                position += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        Err(LexError::UnterminatedComment {
+            span: start..self.input.len(),
+        })
+    }
+
+    /// Takes the next `len` bytes as a token.
+    pub fn take(&mut self, len: usize) -> (usize, &'input str, usize) {
+        let start = self.position;
+        self.position += len;
+        self.last = Some(self.position);
+        (start, &self.input[start..self.position], self.position)
+    }
+
+    /// Takes a token of the characters matching `f`.
+    pub fn take_while(&mut self, f: impl Fn(char) -> bool) -> (usize, &'input str, usize) {
+        let rest = &self.input[self.position..];
+        self.take(rest.find(|c| !f(c)).unwrap_or(rest.len()))
+    }
+
+    /// Whether the input continues with `text` at the next token.
+    pub fn starts_with(&self, text: &str) -> bool {
+        self.input[self.position..].starts_with(text)
+    }
+
+    /// Reports an invalid token at the next token and stops lexing.
+    pub fn invalid(&mut self) -> LexError {
+        let location = self.position;
+        self.position = self.input.len();
+        LexError::InvalidToken { location }
+    }
+}
+
+/// Whether a character can continue a name.
+pub fn is_name(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_comments() {
+        let input = "-- a\nx # b\n{- c {- d -} -} y {- e -}\n";
+        let mut scanner = Scanner::new(input);
+        assert_eq!(scanner.next_token(), Ok(Some('x')));
+        scanner.take(1);
+        assert_eq!(scanner.next_token(), Ok(Some('y')));
+        scanner.take(1);
+        assert_eq!(scanner.next_token(), Ok(None));
+        assert_eq!(
+            scanner.comments,
+            vec![
+                Comment {
+                    span: 0..4,
+                    trailing: false,
+                },
+                Comment {
+                    span: 7..10,
+                    trailing: true,
+                },
+                Comment {
+                    span: 11..26,
+                    trailing: false,
+                },
+                Comment {
+                    span: 29..36,
+                    trailing: true,
+                },
+            ]
+        );
+
+        let mut scanner = Scanner::new("x {- {- -}");
+        scanner.take(1);
+        assert_eq!(
+            scanner.next_token(),
+            Err(LexError::UnterminatedComment { span: 2..10 })
+        );
+    }
+}
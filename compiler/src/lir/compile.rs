@@ -1,67 +1,248 @@
-use super::*;
+use super::{symbol::SymbolTable, *};
+use crate::report::{Diagnostic, Level};
 use inkwell::{
-    basic_block::BasicBlock,
+    attributes::AttributeLoc,
     builder::Builder,
     context::Context,
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DIType,
+        DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
     memory_buffer::MemoryBuffer,
-    module::{Linkage, Module},
+    module::{FlagBehavior, Linkage, Module},
     passes::PassBuilderOptions,
-    targets::{FileType, InitializationConfig, Target, TargetMachine, TargetMachineOptions},
+    targets::{
+        FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetMachineOptions,
+        TargetTriple,
+    },
     types::{BasicMetadataTypeEnum, BasicTypeEnum, FunctionType, StructType},
     values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, PointerValue},
     AddressSpace, OptimizationLevel,
 };
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    mem::MaybeUninit,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-const RTS_BC: &[u8] = include_bytes!("../../../target/rts.bc");
+const RTS_BC: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/rts.bc"));
 
 #[derive(Debug)]
 pub struct Config {
     pub mode: Mode,
     pub opt_level: OptLevel,
+    /// A pass pipeline to run instead of the one chosen by `opt_level`, in the
+    /// syntax of `opt --passes`.
+    pub passes: Option<String>,
+    /// The artifacts to write after optimizing.
+    pub emit: Vec<Emit>,
+    /// Whether to write the artifacts before optimizing instead.
+    pub emit_unoptimized: bool,
+    /// Where to write the executable. Other artifacts are written next to it
+    /// with their own extension.
+    pub output: PathBuf,
+    /// The target triple, which defaults to the host.
+    pub target: Option<String>,
+    /// The CPU to generate code for, which defaults to a generic one.
+    pub cpu: Option<String>,
+    /// LLVM target features, like `+avx2,-sse4.1`.
+    pub features: Option<String>,
+    /// The source to describe the program in terms of with DWARF debug info,
+    /// which is left out if this isn't given.
+    pub debug: Option<Source>,
+}
+
+/// The text a program was parsed from, which its spans are offsets into.
+#[derive(Debug)]
+pub struct Source {
+    pub path: PathBuf,
+    pub text: String,
 }
 
 #[derive(Debug)]
 pub enum Mode {
     Jit,
+    /// Like `Jit`, but reads back the result of `main` as a term, forcing its
+    /// arguments to a depth if one is given.
+    Print {
+        depth: Option<usize>,
+    },
     Aot,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum OptLevel {
     O0,
     O1,
     O2,
     O3,
+    /// Like `O2`, but avoids optimizations that make code larger.
+    Os,
+    /// Like `Os`, but tries even harder to make code small.
+    Oz,
 }
 
-#[derive(Debug)]
+impl OptLevel {
+    pub const ALL: [OptLevel; 6] = [
+        OptLevel::O0,
+        OptLevel::O1,
+        OptLevel::O2,
+        OptLevel::O3,
+        OptLevel::Os,
+        OptLevel::Oz,
+    ];
+
+    /// The level used for code generation and the JIT, which have no separate
+    /// levels for size.
+    fn codegen(&self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os | OptLevel::Oz => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Output {
     ExitCode(i32),
+    Term(Value),
     Binary,
 }
 
+/// A term read back from the result of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Ctor(Name, Vec<Value>),
+    /// A partial application, which can't be printed.
+    Fun,
+    /// An argument below the depth limit.
+    Elided,
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Ctor(name, args) => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    match arg {
+                        Value::Ctor(_, args) if !args.is_empty() => write!(f, " ({})", arg)?,
+                        _ => write!(f, " {}", arg)?,
+                    }
+                }
+                Ok(())
+            }
+            Value::Fun => write!(f, "<function>"),
+            Value::Elided => write!(f, ".."),
+        }
+    }
+}
+
+/// The layout of a term in the RTS, for reading back results.
+#[repr(C)]
+#[allow(dead_code)]
+struct Term {
+    fun: extern "C" fn(*mut Term),
+    args: *mut Term,
+    symbol: u32,
+    length: u16,
+    capacity: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    UnknownLocal { name: Name, span: Span },
+    UnknownGlobal { name: Name, span: Span },
+    NotCtor { name: Name, span: Span },
+    Target(String),
+    Passes(String),
+    Verify(String, String),
+    Write { path: PathBuf, message: String },
+    Link(String),
+}
+
+impl From<CompileError> for Diagnostic {
+    fn from(error: CompileError) -> Self {
+        match error {
+            CompileError::UnknownLocal { name, span } => {
+                Diagnostic::new(Level::Error, format!("no local with name {}", name))
+                    .with_label(span, format!("{} is not defined here", name))
+            }
+            CompileError::UnknownGlobal { name, span } => {
+                Diagnostic::new(Level::Error, format!("no global with name {}", name))
+                    .with_label(span, format!("{} is not defined", name))
+            }
+            CompileError::NotCtor { name, span } => {
+                Diagnostic::new(Level::Error, format!("{} is not a constructor", name))
+                    .with_label(span, "switch cases must be constructors")
+            }
+            CompileError::Target(message) => Diagnostic::new(Level::Error, message),
+            CompileError::Passes(message) => {
+                Diagnostic::new(Level::Error, format!("invalid pass pipeline: {}", message))
+            }
+            CompileError::Verify(message, ir) => Diagnostic::new(
+                Level::Error,
+                format!("LLVM verify error:\n{}\nin the module:\n{}", message, ir),
+            ),
+            CompileError::Write { path, message } => Diagnostic::new(
+                Level::Error,
+                format!("could not write {}: {}", path.display(), message),
+            ),
+            CompileError::Link(message) => Diagnostic::new(
+                Level::Error,
+                format!("failed to link executable: {}", message),
+            ),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             mode: Mode::Jit,
             opt_level: OptLevel::O0,
+            passes: None,
+            emit: vec![],
+            emit_unoptimized: false,
+            output: PathBuf::from("a.out"),
+            target: None,
+            cpu: None,
+            features: None,
+            debug: None,
         }
     }
 }
 
-pub fn compile(program: &Program, config: Config) -> Output {
-    Target::initialize_all(&InitializationConfig::default());
-    let triple = TargetMachine::get_default_triple();
-    let target = Target::from_triple(&triple).unwrap();
-    let options = TargetMachineOptions::new().set_level(OptimizationLevel::None);
-    let machine = target
-        .create_target_machine_from_options(&triple, options)
-        .unwrap();
+pub fn compile(program: &Program, config: Config) -> Result<Output, CompileError> {
+    let machine = target_machine(&config)?;
 
     let context = Context::create();
     let buffer = MemoryBuffer::create_from_memory_range(RTS_BC, "main");
     let module = Module::parse_bitcode_from_buffer(&buffer, &context).unwrap();
+    // The RTS bitcode is built for the host, so it has to be retargeted. Its
+    // code only depends on the layout of `Term`, which is the same on every
+    // 64 bit target. Its functions are left to the CPU and features of the
+    // target machine rather than the host's.
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    for fun in module.get_functions() {
+        for key in ["target-cpu", "target-features", "tune-cpu"] {
+            fun.remove_string_attribute(AttributeLoc::Function, key);
+        }
+    }
     let builder = context.create_builder();
 
     let term_type = context.opaque_struct_type("Term");
@@ -83,9 +264,16 @@ pub fn compile(program: &Program, config: Config) -> Output {
         false,
     );
 
+    let (symbols, _) = symbol::intern(program);
+
+    let debug = config
+        .debug
+        .as_ref()
+        .map(|source| debug_info(source, &config, &context, &module));
+
     let mut unit = Unit {
         config,
-        program,
+        symbols,
         machine,
         context: &context,
         module,
@@ -93,9 +281,10 @@ pub fn compile(program: &Program, config: Config) -> Output {
         term_type,
         fun_type,
         fun: None,
-        block: None,
         arg: None,
         locals: Vec::new(),
+        scrutinees: Vec::new(),
+        debug,
     };
 
     program.iter().for_each(|(name, global)| {
@@ -103,33 +292,84 @@ pub fn compile(program: &Program, config: Config) -> Output {
             define_const(name, global, &mut unit);
         }
     });
+    define_symbol_names(&unit);
 
-    program.iter().for_each(|(name, global)| match global {
-        Global::Fun { block, .. } if name == "main" => define_main(block, &mut unit),
-        Global::Fun { block, .. } => define_fun(name, block, &mut unit),
-        _ => {}
-    });
+    for (name, global) in program {
+        match global {
+            Global::Fun { block, span, .. } if name == "main" => {
+                define_main(block, span, &mut unit)?
+            }
+            Global::Fun { block, span, .. } => define_fun(name, block, span, &mut unit)?,
+            _ => {}
+        }
+    }
 
-    opt(&unit);
+    if let Some(debug) = &unit.debug {
+        debug.builder.finalize();
+    }
 
+    // Passes assume that they are given valid IR, so it is checked first. The
+    // IR is still written if it was asked for, since it shows what is wrong.
     if let Err(e) = unit.module.verify() {
-        unit.print();
-        panic!("LLVM verify error:\n{}", e.to_string());
-    };
+        emit(&unit, &[Emit::LlvmIr, Emit::LlvmBc])?;
+        let ir = unit.module.print_to_string().to_string();
+        return Err(CompileError::Verify(e.to_string(), ir));
+    }
+
+    if unit.config.emit_unoptimized {
+        emit(&unit, &unit.config.emit)?;
+    }
+    opt(&unit)?;
+    if !unit.config.emit_unoptimized {
+        emit(&unit, &unit.config.emit)?;
+    }
 
     match unit.config.mode {
-        Mode::Jit => Output::ExitCode(jit(&unit)),
-        Mode::Aot => {
-            binary(&unit);
-            Output::Binary
-        }
+        Mode::Jit | Mode::Print { .. } => Ok(jit(&unit)),
+        Mode::Aot => Ok(Output::Binary),
+    }
+}
+
+fn target_machine(config: &Config) -> Result<TargetMachine, CompileError> {
+    Target::initialize_all(&InitializationConfig::default());
+    let host = TargetMachine::get_default_triple();
+    let triple = match &config.target {
+        Some(target) => TargetTriple::create(target),
+        None => TargetMachine::get_default_triple(),
+    };
+    if matches!(config.mode, Mode::Jit | Mode::Print { .. })
+        && TargetMachine::normalize_triple(&triple) != TargetMachine::normalize_triple(&host)
+    {
+        return Err(CompileError::Target(format!(
+            "can't evaluate a program compiled for {}",
+            triple
+        )));
+    }
+
+    let target = Target::from_triple(&triple).map_err(|e| CompileError::Target(e.to_string()))?;
+    let options = TargetMachineOptions::new()
+        .set_cpu(config.cpu.as_deref().unwrap_or(""))
+        .set_features(config.features.as_deref().unwrap_or(""))
+        .set_level(config.opt_level.codegen())
+        .set_reloc_mode(RelocMode::PIC);
+    let machine = target
+        .create_target_machine_from_options(&triple, options)
+        .ok_or_else(|| CompileError::Target(format!("can't generate code for {}", triple)))?;
+
+    // The RTS and the layout of `Term` assume 64 bit pointers.
+    if machine.get_target_data().get_pointer_byte_size(None) != 8 {
+        return Err(CompileError::Target(format!(
+            "can't generate code for {}, since only 64 bit targets are supported",
+            triple
+        )));
     }
+    Ok(machine)
 }
 
 #[derive(Debug)]
 struct Unit<'ctx> {
     config: Config,
-    program: &'ctx Program,
+    symbols: SymbolTable,
     machine: TargetMachine,
     context: &'ctx Context,
     module: Module<'ctx>,
@@ -137,16 +377,157 @@ struct Unit<'ctx> {
     term_type: StructType<'ctx>,
     fun_type: FunctionType<'ctx>,
     fun: Option<FunctionValue<'ctx>>,
-    block: Option<BasicBlock<'ctx>>,
     arg: Option<PointerValue<'ctx>>,
     locals: Vec<HashMap<Name, PointerValue<'ctx>>>,
+    /// The terms switched on by the switches around the current block.
+    scrutinees: Vec<PointerValue<'ctx>>,
+    debug: Option<DebugInfo<'ctx>>,
+}
+
+#[derive(Debug)]
+struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    optimized: bool,
+    /// The offsets at which the lines of the source start.
+    lines: Vec<usize>,
+    int_type: DIType<'ctx>,
+    term_type: DIType<'ctx>,
+    term_pointer_type: DIType<'ctx>,
+}
+
+impl DebugInfo<'_> {
+    /// The line and column of an offset into the source, counting from 1.
+    fn position(&self, offset: usize) -> (u32, u32) {
+        let line = self.lines.partition_point(|start| *start <= offset);
+        let column = offset - self.lines[line - 1] + 1;
+        (line as u32, column as u32)
+    }
+}
+
+/// Starts describing a module compiled from a source, with the types its
+/// functions are described with.
+fn debug_info<'ctx>(
+    source: &Source,
+    config: &Config,
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+) -> DebugInfo<'ctx> {
+    // The RTS might have been built with debug info too.
+    if module.get_flag("Debug Info Version").is_none() {
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+    }
+
+    let path = std::path::absolute(&source.path).unwrap_or_else(|_| source.path.clone());
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let directory = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+    let optimized = config.passes.is_some() || !matches!(config.opt_level, OptLevel::O0);
+    // DWARF has no language code for Yoke, and C is what debuggers handle best.
+    let (builder, compile_unit) = module.create_debug_info_builder(
+        true,
+        DWARFSourceLanguage::C,
+        &file_name,
+        &directory,
+        concat!("yoke ", env!("CARGO_PKG_VERSION")),
+        optimized,
+        "",
+        0,
+        "",
+        DWARFEmissionKind::Full,
+        0,
+        false,
+        false,
+        "",
+        "",
+    );
+
+    // Like the `Term` of the RTS, laid out for a 64 bit target, which is the
+    // only kind `target_machine` accepts.
+    let file = compile_unit.get_file();
+    let scope = compile_unit.as_debug_info_scope();
+    let basic_type = |name, size, encoding| {
+        builder
+            .create_basic_type(name, size, encoding, DIFlags::ZERO)
+            .unwrap()
+            .as_type()
+    };
+    let pointer_type = basic_type("ptr", 64, DW_ATE_ADDRESS);
+    let members = [
+        ("fun", pointer_type),
+        ("args", pointer_type),
+        ("symbol", basic_type("u32", 32, DW_ATE_UNSIGNED)),
+        ("length", basic_type("u16", 16, DW_ATE_UNSIGNED)),
+        ("capacity", basic_type("u16", 16, DW_ATE_UNSIGNED)),
+    ];
+    let mut offset = 0;
+    let members = members
+        .iter()
+        .map(|(name, ty)| {
+            let size = ty.get_size_in_bits();
+            let member = builder.create_member_type(
+                scope,
+                name,
+                file,
+                0,
+                size,
+                size as u32,
+                offset,
+                DIFlags::ZERO,
+                *ty,
+            );
+            offset += size;
+            member.as_type()
+        })
+        .collect::<Vec<_>>();
+    let term_type = builder
+        .create_struct_type(
+            scope,
+            "Term",
+            file,
+            0,
+            offset,
+            64,
+            DIFlags::ZERO,
+            None,
+            &members,
+            0,
+            None,
+            "Term",
+        )
+        .as_type();
+    let term_pointer_type = builder
+        .create_pointer_type("Term *", term_type, 64, 64, AddressSpace::from(0))
+        .as_type();
+
+    DebugInfo {
+        lines: std::iter::once(0)
+            .chain(source.text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect(),
+        int_type: basic_type("int", 32, DW_ATE_SIGNED),
+        builder,
+        compile_unit,
+        optimized,
+        term_type,
+        term_pointer_type,
+    }
 }
 
+const DW_ATE_ADDRESS: u32 = 0x01;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+
 fn define_const(name: &str, global: &Global, unit: &mut Unit) {
     match global {
-        Global::Ctor { arity, symbol } => {
+        Global::Ctor { arity, .. } => {
             let noop = unit.module.get_function("noop").unwrap();
-            add_global(noop, format!("term_{name}"), *symbol, *arity, unit);
+            let symbol = unit.symbols.symbol(name).unwrap();
+            add_global(noop, format!("term_{name}"), symbol, *arity, unit);
         }
         Global::Fun { arity, .. } => {
             let fun = unit.module.add_function(
@@ -159,27 +540,75 @@ fn define_const(name: &str, global: &Global, unit: &mut Unit) {
     }
 }
 
-fn define_main(main: &Block, unit: &mut Unit) {
-    let main_fun_type = unit.context.i32_type().fn_type(&[], false);
+/// Defines a table of the names of constructors, which the RTS uses to report
+/// unhandled cases.
+fn define_symbol_names(unit: &Unit) {
+    let mut symbols = unit.symbols.iter().collect::<Vec<_>>();
+    symbols.sort_by_key(|(_, symbol)| *symbol);
+
+    let entry_type = unit.context.struct_type(
+        &[
+            BasicTypeEnum::IntType(unit.context.i32_type()),
+            BasicTypeEnum::PointerType(unit.context.ptr_type(AddressSpace::from(0))),
+        ],
+        false,
+    );
+    let entries = symbols
+        .iter()
+        .map(|(name, symbol)| {
+            let symbol = unit.context.i32_type().const_int(*symbol as u64, false);
+            let name = add_string(name, unit);
+            entry_type.const_named_struct(&[symbol.into(), name.into()])
+        })
+        .collect::<Vec<_>>();
+
+    let table = unit.module.add_global(
+        entry_type.array_type(entries.len() as u32),
+        None,
+        "symbol_names",
+    );
+    table.set_initializer(&entry_type.const_array(&entries));
+    table.set_linkage(Linkage::Private);
+    table.set_constant(true);
+}
+
+fn add_string<'ctx>(string: &str, unit: &Unit<'ctx>) -> PointerValue<'ctx> {
+    let value = unit.context.const_string(string.as_bytes(), true);
+    let global = unit.module.add_global(value.get_type(), None, "");
+    global.set_initializer(&value);
+    global.set_linkage(Linkage::Private);
+    global.set_constant(true);
+    global.as_pointer_value()
+}
+
+fn define_main(main: &Block, span: &Span, unit: &mut Unit) -> Result<(), CompileError> {
+    // When printing, main also writes its result to a term given by the caller.
+    let params = match unit.config.mode {
+        Mode::Print { .. } => vec![BasicMetadataTypeEnum::PointerType(
+            unit.context.ptr_type(AddressSpace::from(0)),
+        )],
+        _ => vec![],
+    };
+    let main_fun_type = unit.context.i32_type().fn_type(&params, false);
     let fun = unit.module.add_function("main", main_fun_type, None);
     unit.fun = Some(fun);
+    unit.describe_fun("main", span);
 
     let block = unit.context.append_basic_block(fun, "start");
-    unit.block = Some(block);
     unit.builder.position_at_end(block);
 
     unit.clear_locals();
     unit.add_scope();
 
-    compile_block(main, unit);
+    compile_block(main, unit)
 }
 
-fn define_fun(name: &str, block: &Block, unit: &mut Unit) {
+fn define_fun(name: &str, block: &Block, span: &Span, unit: &mut Unit) -> Result<(), CompileError> {
     let fun = unit.module.get_function(&format!("fun_{name}")).unwrap();
     unit.fun = Some(fun);
+    unit.describe_fun(name, span);
 
     let basic_block = unit.context.append_basic_block(fun, "start");
-    unit.block = Some(basic_block);
     unit.builder.position_at_end(basic_block);
 
     unit.clear_locals();
@@ -189,20 +618,24 @@ fn define_fun(name: &str, block: &Block, unit: &mut Unit) {
     unit.arg = Some(arg);
     unit.define("self".to_string(), arg);
 
-    compile_block(block, unit);
+    compile_block(block, unit)
 }
 
-fn compile_block(block: &Block, unit: &mut Unit) {
-    block.iter().for_each(|op| compile_op(op, unit));
+fn compile_block(block: &Block, unit: &mut Unit) -> Result<(), CompileError> {
+    block.iter().try_for_each(|op| compile_op(op, unit))
 }
 
-fn compile_op(op: &Op, unit: &mut Unit) {
+fn compile_op(op: &Op, unit: &mut Unit) -> Result<(), CompileError> {
+    unit.set_location(op.span());
     match op {
-        Op::LoadGlobal { global } => {
+        Op::LoadGlobal { global, span } => {
             let global_value = unit
                 .module
                 .get_global(&format!("term_{}", &global))
-                .unwrap();
+                .ok_or_else(|| CompileError::UnknownGlobal {
+                    name: global.clone(),
+                    span: span.clone(),
+                })?;
             let global_load = unit
                 .builder
                 .build_load(unit.term_type, global_value.as_pointer_value(), "")
@@ -212,8 +645,13 @@ fn compile_op(op: &Op, unit: &mut Unit) {
 
             unit.define(global.clone(), alloca);
         }
-        Op::LoadArg { name, var, index } => {
-            let term = unit.lookup(var);
+        Op::LoadArg {
+            name,
+            var,
+            index,
+            span,
+        } => {
+            let term = unit.lookup(var, span)?;
 
             let term_load = unit
                 .builder
@@ -244,20 +682,23 @@ fn compile_op(op: &Op, unit: &mut Unit) {
             name,
             var,
             ref args,
-        } => compile_apply_call(name.clone(), "new_app", var, args, unit),
+            span,
+        } => compile_apply_call(name.clone(), "new_app", var, args, span, unit)?,
         Op::NewPartial {
             name,
             var,
             ref args,
-        } => compile_apply_call(name.clone(), "new_partial", var, args, unit),
+            span,
+        } => compile_apply_call(name.clone(), "new_partial", var, args, span, unit)?,
         Op::ApplyPartial {
             name,
             var,
             ref args,
-        } => compile_apply_call(name.clone(), "apply_partial", var, args, unit),
-        Op::Copy { name, var } => {
+            span,
+        } => compile_apply_call(name.clone(), "apply_partial", var, args, span, unit)?,
+        Op::Copy { name, var, span } => {
             let dest = unit.builder.build_alloca(unit.term_type, "").unwrap();
-            let src = unit.lookup(var);
+            let src = unit.lookup(var, span)?;
             let copy = unit.module.get_function("copy").unwrap();
             unit.builder
                 .build_call(
@@ -272,8 +713,8 @@ fn compile_op(op: &Op, unit: &mut Unit) {
 
             unit.define(name.clone(), dest);
         }
-        Op::Eval { var } => {
-            let term = unit.lookup(var);
+        Op::Eval { var, span } => {
+            let term = unit.lookup(var, span)?;
             let term_load = unit
                 .builder
                 .build_load(unit.term_type, term, "")
@@ -288,40 +729,50 @@ fn compile_op(op: &Op, unit: &mut Unit) {
                 .build_indirect_call(unit.fun_type, fun, &[term.into()], "")
                 .unwrap();
         }
-        Op::FreeArgs { var } => {
-            let term = unit.lookup(var);
+        Op::FreeArgs { var, span } => {
+            let term = unit.lookup(var, span)?;
             let free_args = unit.module.get_function("free_args").unwrap();
             unit.builder
                 .build_call(free_args, &[BasicMetadataValueEnum::PointerValue(term)], "")
                 .unwrap();
         }
-        Op::FreeTerm { var } => {
-            let term = unit.lookup(var);
+        Op::FreeTerm { var, span } => {
+            let term = unit.lookup(var, span)?;
             let free_term = unit.module.get_function("free_term").unwrap();
             unit.builder
                 .build_call(free_term, &[BasicMetadataValueEnum::PointerValue(term)], "")
                 .unwrap();
         }
-        Op::ReturnSymbol { var } => {
-            let term = unit.lookup(var);
+        Op::ReturnSymbol { var, span } => {
+            let term = unit.lookup(var, span)?;
             let term_load = unit
                 .builder
                 .build_load(unit.term_type, term, "")
                 .unwrap()
                 .into_struct_value();
             let symbol = unit.builder.build_extract_value(term_load, 2, "").unwrap();
+            if let Mode::Print { .. } = unit.config.mode {
+                let result = unit.fun.unwrap().get_first_param().unwrap();
+                unit.builder
+                    .build_store(result.into_pointer_value(), term_load)
+                    .unwrap();
+            }
             unit.builder.build_return(Some(&symbol)).unwrap();
         }
-        Op::Return { var } => {
-            let term = unit.lookup(var);
+        Op::Return { var, span } => {
+            let term = unit.lookup(var, span)?;
             let term_load = unit.builder.build_load(unit.term_type, term, "").unwrap();
             unit.builder
                 .build_store(unit.arg.unwrap(), term_load)
                 .unwrap();
             unit.builder.build_return(None).unwrap();
         }
-        Op::Switch { var, ref cases } => {
-            let term = unit.lookup(var);
+        Op::Switch {
+            var,
+            ref cases,
+            span,
+        } => {
+            let term = unit.lookup(var, span)?;
             let term_load = unit
                 .builder
                 .build_load(unit.term_type, term, "")
@@ -333,50 +784,99 @@ fn compile_op(op: &Op, unit: &mut Unit) {
                 .unwrap()
                 .into_int_value();
 
-            unit.add_scope();
+            let current_block = unit.builder.get_insert_block().unwrap();
 
-            let cases = cases
-                .iter()
-                .map(|case| {
-                    let Global::Ctor { symbol, .. } = unit.program.get(&case.global).unwrap()
-                    else {
-                        panic!()
-                    };
-                    let symbol = unit.context.i32_type().const_int(*symbol as u64, false);
-                    let block = unit.context.append_basic_block(unit.fun.unwrap(), "");
-                    unit.builder.position_at_end(block);
-                    unit.clear_scope();
-                    compile_block(&case.block, unit);
-                    (symbol, block)
-                })
-                .collect::<Vec<_>>();
+            unit.scrutinees.push(term);
+            let mut default_case = None;
+            let mut blocks = vec![];
+            for case in cases {
+                let symbol = if case.is_default() {
+                    None
+                } else {
+                    Some(unit.case_symbol(case)?)
+                };
+                let block = unit.context.append_basic_block(unit.fun.unwrap(), "");
+                unit.builder.position_at_end(block);
+                unit.add_scope();
+                compile_block(&case.block, unit)?;
+                unit.remove_scope();
+                match symbol {
+                    Some(symbol) => {
+                        let symbol = unit.context.i32_type().const_int(symbol as u64, false);
+                        blocks.push((symbol, block));
+                    }
+                    None => default_case = Some(block),
+                }
+            }
+            unit.scrutinees.pop();
 
-            let default_case = unit
-                .context
-                .append_basic_block(unit.fun.unwrap(), "default");
-            unit.builder.position_at_end(default_case);
-            unit.builder.build_unreachable().unwrap();
+            // A switch without a case for the symbol acts like `todo`.
+            let default_case = match default_case {
+                Some(block) => block,
+                None => {
+                    let block = unit
+                        .context
+                        .append_basic_block(unit.fun.unwrap(), "default");
+                    unit.builder.position_at_end(block);
+                    compile_todo(Some(term), unit);
+                    block
+                }
+            };
 
-            unit.builder.position_at_end(unit.block.unwrap());
+            unit.builder.position_at_end(current_block);
+            unit.set_location(span);
             unit.builder
-                .build_switch(symbol, default_case, &cases)
+                .build_switch(symbol, default_case, &blocks)
                 .unwrap();
         }
-        Op::Todo => {
-            let todo = unit.module.get_function("todo").unwrap();
-            unit.builder.build_call(todo, &[], "").unwrap();
-            unit.builder.build_unreachable().unwrap();
+        Op::Todo { .. } => {
+            let scrutinee = unit.scrutinees.last().copied();
+            compile_todo(scrutinee, unit);
         }
     }
+    Ok(())
 }
 
-fn compile_apply_call(name: Name, fun_name: &str, var: &str, args: &[String], unit: &mut Unit) {
-    let term = unit.lookup(var);
+/// Reports an unhandled case in the current function, switching on
+/// `scrutinee` if there is one, and exits.
+fn compile_todo<'ctx>(scrutinee: Option<PointerValue<'ctx>>, unit: &Unit<'ctx>) {
+    let todo = unit.module.get_function("todo").unwrap();
+    let fun = unit.fun.unwrap().get_name().to_str().unwrap().to_string();
+    let fun = add_string(fun.strip_prefix("fun_").unwrap_or(&fun), unit);
+    let scrutinee =
+        scrutinee.unwrap_or_else(|| unit.context.ptr_type(AddressSpace::from(0)).const_null());
+    let names = unit.module.get_global("symbol_names").unwrap();
+    let count = names.get_value_type().into_array_type().len();
+    let count = unit.context.i64_type().const_int(count as u64, false);
+    unit.builder
+        .build_call(
+            todo,
+            &[
+                fun.into(),
+                scrutinee.into(),
+                names.as_pointer_value().into(),
+                count.into(),
+            ],
+            "",
+        )
+        .unwrap();
+    unit.builder.build_unreachable().unwrap();
+}
+
+fn compile_apply_call(
+    name: Name,
+    fun_name: &str,
+    var: &str,
+    args: &[String],
+    span: &Span,
+    unit: &mut Unit,
+) -> Result<(), CompileError> {
+    let term = unit.lookup(var, span)?;
     let length_constant = unit.context.i64_type().const_int(args.len() as u64, false);
     let args_type = unit.term_type.array_type(args.len() as u32);
     let args_alloca = unit.builder.build_alloca(args_type, "").unwrap();
     for (i, arg) in args.iter().enumerate() {
-        let arg_local = unit.lookup(arg);
+        let arg_local = unit.lookup(arg, span)?;
         let arg_load = unit
             .builder
             .build_load(unit.term_type, arg_local, "")
@@ -403,6 +903,7 @@ fn compile_apply_call(name: Name, fun_name: &str, var: &str, args: &[String], un
         .unwrap();
 
     unit.define(name, term);
+    Ok(())
 }
 
 fn add_global(fun: FunctionValue, name: Name, symbol: Symbol, arity: Arity, unit: &mut Unit) {
@@ -424,32 +925,136 @@ fn add_global(fun: FunctionValue, name: Name, symbol: Symbol, arity: Arity, unit
     global.set_initializer(&struct_val);
 }
 
-fn opt(unit: &Unit) {
-    let pass = match unit.config.opt_level {
-        OptLevel::O0 => return,
-        OptLevel::O1 => "default<O1>",
-        OptLevel::O2 => "default<O2>",
-        OptLevel::O3 => "default<O3>",
+fn opt(unit: &Unit) -> Result<(), CompileError> {
+    let passes = match (&unit.config.passes, &unit.config.opt_level) {
+        (Some(passes), _) => passes.as_str(),
+        (None, OptLevel::O0) => return Ok(()),
+        (None, OptLevel::O1) => "default<O1>",
+        (None, OptLevel::O2) => "default<O2>",
+        (None, OptLevel::O3) => "default<O3>",
+        (None, OptLevel::Os) => "default<Os>",
+        (None, OptLevel::Oz) => "default<Oz>",
     };
     unit.module
-        .run_passes(pass, &unit.machine, PassBuilderOptions::create())
-        .unwrap();
+        .run_passes(passes, &unit.machine, PassBuilderOptions::create())
+        .map_err(|e| CompileError::Passes(e.to_string()))
 }
 
-fn jit(unit: &Unit) -> i32 {
+fn jit(unit: &Unit) -> Output {
     let engine = unit
         .module
-        .create_jit_execution_engine(OptimizationLevel::None)
+        .create_jit_execution_engine(unit.config.opt_level.codegen())
         .unwrap();
-    type MainFun = unsafe extern "C" fn() -> i32;
-    let main_fun = unsafe { engine.get_function::<MainFun>("main") }.unwrap();
-    unsafe { main_fun.call() }
+    match unit.config.mode {
+        Mode::Print { depth } => {
+            type MainFun = unsafe extern "C" fn(*mut Term) -> i32;
+            let main_fun = unsafe { engine.get_function::<MainFun>("main") }.unwrap();
+            let mut result = MaybeUninit::<Term>::uninit();
+            unsafe { main_fun.call(result.as_mut_ptr()) };
+            Output::Term(read_back(result.as_mut_ptr(), depth, &unit.symbols))
+        }
+        _ => {
+            type MainFun = unsafe extern "C" fn() -> i32;
+            let main_fun = unsafe { engine.get_function::<MainFun>("main") }.unwrap();
+            Output::ExitCode(unsafe { main_fun.call() })
+        }
+    }
+}
+
+/// Evaluates the arguments of an evaluated term, down to `depth`, and reads
+/// it back with the names of its constructors. The functions in the terms
+/// belong to the JIT, so it has to be alive.
+fn read_back(term: *mut Term, depth: Option<usize>, symbols: &SymbolTable) -> Value {
+    let term = unsafe { &*term };
+    if term.symbol == 0 {
+        return Value::Fun;
+    }
+
+    let name = match symbols.name(term.symbol) {
+        Some(name) => name.to_string(),
+        None => term.symbol.to_string(),
+    };
+    let args = (0..term.length as usize)
+        .map(|i| match depth {
+            Some(0) => Value::Elided,
+            _ => {
+                let arg = unsafe { term.args.add(i) };
+                (unsafe { &*arg }.fun)(arg);
+                read_back(arg, depth.map(|depth| depth - 1), symbols)
+            }
+        })
+        .collect();
+    Value::Ctor(name, args)
+}
+
+/// Writes the artifacts in `kinds` that were asked for.
+fn emit(unit: &Unit, kinds: &[Emit]) -> Result<(), CompileError> {
+    for emit in unit.config.emit.iter().filter(|emit| kinds.contains(emit)) {
+        let path = match emit {
+            Emit::LlvmIr => unit.config.output.with_extension("ll"),
+            Emit::LlvmBc => unit.config.output.with_extension("bc"),
+            Emit::Asm => unit.config.output.with_extension("s"),
+            Emit::Obj => unit.config.output.with_extension("o"),
+            Emit::Exe => {
+                binary(unit)?;
+                continue;
+            }
+        };
+        let result = match emit {
+            Emit::LlvmIr => unit.module.print_to_file(&path).map_err(|e| e.to_string()),
+            Emit::LlvmBc if unit.module.write_bitcode_to_path(&path) => Ok(()),
+            Emit::LlvmBc => Err("failed to write bitcode".to_string()),
+            Emit::Asm => write_file(unit, FileType::Assembly, &path),
+            Emit::Obj => write_file(unit, FileType::Object, &path),
+            Emit::Exe => unreachable!(),
+        };
+        result.map_err(|message| CompileError::Write { path, message })?;
+    }
+    Ok(())
 }
 
-fn binary(unit: &Unit) {
+fn write_file(unit: &Unit, file_type: FileType, path: &Path) -> Result<(), String> {
     unit.machine
-        .write_to_file(&unit.module, FileType::Object, Path::new("main.o"))
-        .unwrap();
+        .write_to_file(&unit.module, file_type, path)
+        .map_err(|e| e.to_string())
+}
+
+/// Writes a temporary object file and links it with the C compiler, which
+/// knows where to find libc and the startup files. `$CC` overrides the
+/// compiler, which defaults to `cc`, and has to be set to a cross compiler
+/// when targeting another platform.
+fn binary(unit: &Unit) -> Result<(), CompileError> {
+    // Several programs can be compiled at once by one process, like the tests.
+    static OBJECTS: AtomicUsize = AtomicUsize::new(0);
+    let output = &unit.config.output;
+    let object = std::env::temp_dir().join(format!(
+        "yoke-{}-{}.o",
+        std::process::id(),
+        OBJECTS.fetch_add(1, Ordering::Relaxed)
+    ));
+    write_file(unit, FileType::Object, &object).map_err(|message| CompileError::Write {
+        path: object.clone(),
+        message,
+    })?;
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&cc)
+        .arg(&object)
+        .arg("-o")
+        .arg(output)
+        .output();
+    let _ = std::fs::remove_file(&object);
+
+    match result {
+        Ok(result) if result.status.success() => Ok(()),
+        Ok(result) => Err(CompileError::Link(format!(
+            "{} exited with {}\n{}",
+            cc,
+            result.status,
+            String::from_utf8_lossy(&result.stderr)
+        ))),
+        Err(e) => Err(CompileError::Link(format!("could not run {}: {}", cc, e))),
+    }
 }
 
 impl<'ctx> Unit<'ctx> {
@@ -458,29 +1063,127 @@ impl<'ctx> Unit<'ctx> {
     }
 
     fn define(&mut self, name: Name, value: PointerValue<'ctx>) {
+        self.declare(&name, value);
         self.locals.last_mut().unwrap().insert(name, value);
     }
 
-    fn lookup(&self, var: &str) -> PointerValue<'ctx> {
+    /// Attaches a subprogram to the current function, which has a name and
+    /// span in the source, if debug info is enabled.
+    fn describe_fun(&self, name: &str, span: &Span) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let fun = self.fun.unwrap();
+
+        let (result, params) = match (name, &self.config.mode) {
+            ("main", Mode::Print { .. }) => (Some(debug.int_type), vec![debug.term_pointer_type]),
+            ("main", _) => (Some(debug.int_type), vec![]),
+            _ => (None, vec![debug.term_pointer_type]),
+        };
+        let file = debug.compile_unit.get_file();
+        let fun_type = debug
+            .builder
+            .create_subroutine_type(file, result, &params, DIFlags::ZERO);
+        let (line, _) = debug.position(span.start);
+        let subprogram = debug.builder.create_function(
+            debug.compile_unit.as_debug_info_scope(),
+            name,
+            fun.get_name().to_str().ok(),
+            file,
+            line,
+            fun_type,
+            name != "main",
+            true,
+            line,
+            DIFlags::ZERO,
+            debug.optimized,
+        );
+        fun.set_subprogram(subprogram);
+        self.set_location(span);
+    }
+
+    /// Attributes the instructions built from now on to a span of the source,
+    /// if debug info is enabled.
+    fn set_location(&self, span: &Span) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let (line, column) = debug.position(span.start);
+        let scope = self.fun.unwrap().get_subprogram().unwrap();
+        let location = debug.builder.create_debug_location(
+            self.context,
+            line,
+            column,
+            scope.as_debug_info_scope(),
+            None,
+        );
+        self.builder.set_current_debug_location(location);
+    }
+
+    /// Describes a local as a variable at the current location, if debug info
+    /// is enabled.
+    fn declare(&self, name: &str, value: PointerValue<'ctx>) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let location = self.builder.get_current_debug_location().unwrap();
+        let variable = debug.builder.create_auto_variable(
+            location.get_scope(),
+            name,
+            debug.compile_unit.get_file(),
+            location.get_line(),
+            debug.term_type,
+            true,
+            DIFlags::ZERO,
+            0,
+        );
+        debug.builder.insert_declare_at_end(
+            value,
+            Some(variable),
+            None,
+            location,
+            self.builder.get_insert_block().unwrap(),
+        );
+    }
+
+    fn lookup(&self, var: &str, span: &Span) -> Result<PointerValue<'ctx>, CompileError> {
         for scope in self.locals.iter().rev() {
             if let Some(local) = scope.get(var) {
-                return *local;
+                return Ok(*local);
             }
         }
-        panic!("no local with name: {}", var)
+        Err(CompileError::UnknownLocal {
+            name: var.to_string(),
+            span: span.clone(),
+        })
     }
 
-    fn clear_locals(&mut self) {
-        self.locals.clear()
+    fn case_symbol(&self, case: &Case) -> Result<Symbol, CompileError> {
+        match self.symbols.symbol(&case.global) {
+            Some(symbol) => Ok(symbol),
+            None if self
+                .module
+                .get_global(&format!("term_{}", case.global))
+                .is_some() =>
+            {
+                Err(CompileError::NotCtor {
+                    name: case.global.clone(),
+                    span: case.span.clone(),
+                })
+            }
+            None => Err(CompileError::UnknownGlobal {
+                name: case.global.clone(),
+                span: case.span.clone(),
+            }),
+        }
     }
 
-    fn clear_scope(&mut self) {
-        self.locals.last_mut().unwrap().clear()
+    fn clear_locals(&mut self) {
+        self.locals.clear()
     }
 
-    fn print(&self) {
-        let s = self.module.print_to_string().to_string();
-        eprintln!("{}", s);
+    fn remove_scope(&mut self) {
+        self.locals.pop();
     }
 }
 
@@ -490,7 +1193,7 @@ mod test {
 
     macro_rules! test {
         ($prog:expr, $expected:expr) => {
-            let Output::ExitCode(result) = compile(&$prog, Config::default()) else {
+            let Ok(Output::ExitCode(result)) = compile(&$prog, Config::default()) else {
                 panic!()
             };
             assert_eq!(result, $expected);
@@ -500,12 +1203,13 @@ mod test {
     #[test]
     fn test_return_symbol() {
         test!(
-            HashMap::from([
+            Program::from([
                 (
                     "True".to_string(),
                     Global::Ctor {
                         arity: 0,
-                        symbol: 1,
+                        symbol: Some(1),
+                        span: 0..0,
                     }
                 ),
                 (
@@ -515,11 +1219,14 @@ mod test {
                         block: vec![
                             Op::LoadGlobal {
                                 global: "True".to_string(),
+                                span: 0..0,
                             },
                             Op::ReturnSymbol {
-                                var: "True".to_string()
+                                var: "True".to_string(),
+                                span: 0..0
                             },
-                        ]
+                        ],
+                        span: 0..0
                     }
                 )
             ]),
@@ -530,12 +1237,13 @@ mod test {
     #[test]
     fn test_copy() {
         test!(
-            HashMap::from([
+            Program::from([
                 (
                     "True".to_string(),
                     Global::Ctor {
                         arity: 0,
-                        symbol: 1,
+                        symbol: Some(1),
+                        span: 0..0,
                     },
                 ),
                 (
@@ -545,18 +1253,23 @@ mod test {
                         block: vec![
                             Op::LoadGlobal {
                                 global: "True".to_string(),
+                                span: 0..0,
                             },
                             Op::Copy {
                                 name: "x".to_string(),
                                 var: "True".to_string(),
+                                span: 0..0,
                             },
                             Op::FreeTerm {
-                                var: "x".to_string()
+                                var: "x".to_string(),
+                                span: 0..0
                             },
                             Op::ReturnSymbol {
-                                var: "x".to_string()
+                                var: "x".to_string(),
+                                span: 0..0
                             },
-                        ]
+                        ],
+                        span: 0..0
                     }
                 )
             ]),
@@ -567,12 +1280,13 @@ mod test {
     #[test]
     fn test_id() {
         test!(
-            HashMap::from([
+            Program::from([
                 (
                     "True".to_string(),
                     Global::Ctor {
                         arity: 0,
-                        symbol: 1,
+                        symbol: Some(1),
+                        span: 0..0,
                     },
                 ),
                 (
@@ -584,17 +1298,22 @@ mod test {
                                 name: "x".to_string(),
                                 var: "self".to_string(),
                                 index: 0,
+                                span: 0..0,
                             },
                             Op::FreeArgs {
-                                var: "self".to_string()
+                                var: "self".to_string(),
+                                span: 0..0
                             },
                             Op::Eval {
                                 var: "x".to_string(),
+                                span: 0..0,
                             },
                             Op::Return {
-                                var: "x".to_string()
+                                var: "x".to_string(),
+                                span: 0..0
                             },
                         ],
+                        span: 0..0,
                     }
                 ),
                 (
@@ -604,22 +1323,28 @@ mod test {
                         block: vec![
                             Op::LoadGlobal {
                                 global: "id".to_string(),
+                                span: 0..0,
                             },
                             Op::LoadGlobal {
                                 global: "True".to_string(),
+                                span: 0..0,
                             },
                             Op::NewApp {
                                 name: "result".to_string(),
                                 var: "id".to_string(),
-                                args: vec!["True".to_string()]
+                                args: vec!["True".to_string()],
+                                span: 0..0
                             },
                             Op::Eval {
                                 var: "result".to_string(),
+                                span: 0..0,
                             },
                             Op::ReturnSymbol {
-                                var: "result".to_string()
+                                var: "result".to_string(),
+                                span: 0..0
                             },
                         ],
+                        span: 0..0,
                     }
                 ),
             ]),
@@ -630,19 +1355,21 @@ mod test {
     #[test]
     fn test_switch() {
         test!(
-            HashMap::from([
+            Program::from([
                 (
                     "True".to_string(),
                     Global::Ctor {
                         arity: 0,
-                        symbol: 1,
+                        symbol: Some(1),
+                        span: 0..0,
                     },
                 ),
                 (
                     "False".to_string(),
                     Global::Ctor {
                         arity: 0,
-                        symbol: 2,
+                        symbol: Some(2),
+                        span: 0..0,
                     },
                 ),
                 (
@@ -652,9 +1379,11 @@ mod test {
                         block: vec![
                             Op::LoadGlobal {
                                 global: "True".to_string(),
+                                span: 0..0,
                             },
                             Op::LoadGlobal {
                                 global: "False".to_string(),
+                                span: 0..0,
                             },
                             Op::Switch {
                                 var: "True".to_string(),
@@ -662,22 +1391,241 @@ mod test {
                                     Case {
                                         global: "True".to_string(),
                                         block: vec![Op::ReturnSymbol {
-                                            var: "False".to_string()
-                                        }]
+                                            var: "False".to_string(),
+                                            span: 0..0
+                                        }],
+                                        span: 0..0
                                     },
                                     Case {
                                         global: "False".to_string(),
                                         block: vec![Op::ReturnSymbol {
-                                            var: "True".to_string()
-                                        }]
+                                            var: "True".to_string(),
+                                            span: 0..0
+                                        }],
+                                        span: 0..0
                                     },
                                 ],
+                                span: 0..0,
                             },
                         ],
+                        span: 0..0,
                     }
                 ),
             ]),
             2
         );
     }
+
+    #[test]
+    fn test_errors() {
+        let error =
+            |input: &str| compile(&parse::parse(input).unwrap(), Config::default()).unwrap_err();
+
+        assert_eq!(
+            error("main = 0 { return_symbol x }"),
+            CompileError::UnknownLocal {
+                name: "x".to_string(),
+                span: 11..26,
+            }
+        );
+
+        assert_eq!(
+            error("main = 0 { load_global f }"),
+            CompileError::UnknownGlobal {
+                name: "f".to_string(),
+                span: 11..24,
+            }
+        );
+
+        assert_eq!(
+            error(
+                "True = 0 f = 0 { todo } main = 0 { load_global True switch True { f { todo } } }"
+            ),
+            CompileError::NotCtor {
+                name: "f".to_string(),
+                span: 66..67,
+            }
+        );
+    }
+
+    #[test]
+    fn test_opt_levels() {
+        let program = parse::parse(
+            "True = 0 7
+             main = 0 { load_global id load_global True x = new_app id { True } eval x return_symbol x }
+             id = 1 { x = load_arg self 0 free_args self eval x return x }",
+        )
+        .unwrap();
+
+        for opt_level in [
+            OptLevel::O0,
+            OptLevel::O1,
+            OptLevel::O2,
+            OptLevel::O3,
+            OptLevel::Os,
+            OptLevel::Oz,
+        ] {
+            let config = Config {
+                opt_level,
+                ..Config::default()
+            };
+            let Ok(Output::ExitCode(result)) = compile(&program, config) else {
+                panic!()
+            };
+            assert_eq!(result, 7);
+        }
+
+        let config = Config {
+            passes: Some("function(mem2reg),globaldce".to_string()),
+            ..Config::default()
+        };
+        let Ok(Output::ExitCode(result)) = compile(&program, config) else {
+            panic!()
+        };
+        assert_eq!(result, 7);
+
+        let config = Config {
+            passes: Some("not-a-pass".to_string()),
+            ..Config::default()
+        };
+        assert!(matches!(
+            compile(&program, config),
+            Err(CompileError::Passes(_))
+        ));
+    }
+
+    #[test]
+    fn test_print() {
+        let program = parse::parse(
+            "True = 0 Nil = 0 Cons = 2 Just = 1
+             main = 0 {
+               load_global id load_global True t = new_app id { True }
+               load_global Cons load_global Nil l = new_app Cons { t Nil }
+               load_global Just j = new_app Just { l }
+               return_symbol j
+             }
+             id = 1 { x = load_arg self 0 free_args self eval x return x }",
+        )
+        .unwrap();
+
+        let print = |depth| {
+            let config = Config {
+                mode: Mode::Print { depth },
+                ..Config::default()
+            };
+            let Ok(Output::Term(value)) = compile(&program, config) else {
+                panic!()
+            };
+            value.to_string()
+        };
+
+        assert_eq!(print(None), "Just (Cons True Nil)");
+        assert_eq!(print(Some(1)), "Just (Cons .. ..)");
+        assert_eq!(print(Some(0)), "Just ..");
+    }
+
+    #[test]
+    fn test_link() {
+        // Each program is linked from its own object, even at the same time.
+        let threads = (1..=4)
+            .map(|symbol| {
+                std::thread::spawn(move || {
+                    let program = parse::parse(&format!(
+                        "True = 0 {} main = 0 {{ load_global True return_symbol True }}",
+                        symbol
+                    ))
+                    .unwrap();
+                    let output = std::env::temp_dir().join(format!(
+                        "yoke-link-{}-{}",
+                        std::process::id(),
+                        symbol
+                    ));
+                    let config = Config {
+                        mode: Mode::Aot,
+                        emit: vec![Emit::Exe],
+                        output: output.clone(),
+                        ..Config::default()
+                    };
+                    assert_eq!(compile(&program, config), Ok(Output::Binary));
+                    let status = Command::new(&output).status().unwrap();
+                    std::fs::remove_file(&output).unwrap();
+                    assert_eq!(status.code(), Some(symbol));
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        let program =
+            parse::parse("True = 0 main = 0 { load_global True return_symbol True }").unwrap();
+        let output = std::env::temp_dir().join(format!("yoke-target-{}", std::process::id()));
+        let config = Config {
+            mode: Mode::Aot,
+            emit: vec![Emit::LlvmIr, Emit::Obj],
+            output: output.clone(),
+            target: Some("aarch64-unknown-linux-gnu".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(compile(&program, config), Ok(Output::Binary));
+
+        let ir = std::fs::read_to_string(output.with_extension("ll")).unwrap();
+        std::fs::remove_file(output.with_extension("ll")).unwrap();
+        assert!(ir.contains("target triple = \"aarch64-unknown-linux-gnu\""));
+        assert!(!ir.contains("target-cpu"));
+        assert!(!ir.contains("target-features"));
+
+        // An ELF file whose machine is AArch64.
+        let object = std::fs::read(output.with_extension("o")).unwrap();
+        std::fs::remove_file(output.with_extension("o")).unwrap();
+        assert_eq!(&object[..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([object[18], object[19]]), 183);
+
+        let config = Config {
+            mode: Mode::Aot,
+            target: Some("i686-unknown-linux-gnu".to_string()),
+            ..Config::default()
+        };
+        assert!(matches!(
+            compile(&program, config),
+            Err(CompileError::Target(_))
+        ));
+    }
+
+    #[test]
+    fn test_debug() {
+        let text = "True = 0 7
+main = 0 { load_global id load_global True x = new_app id { True } eval x return_symbol x }
+id = 1 {
+  x = load_arg self 0
+  free_args self
+  eval x
+  return x
+}";
+        let program = parse::parse(text).unwrap();
+        let output = std::env::temp_dir().join(format!("yoke-debug-{}", std::process::id()));
+        let config = Config {
+            emit: vec![Emit::LlvmIr],
+            output: output.clone(),
+            debug: Some(Source {
+                path: PathBuf::from("id.lir"),
+                text: text.to_string(),
+            }),
+            ..Config::default()
+        };
+        let Ok(Output::ExitCode(result)) = compile(&program, config) else {
+            panic!()
+        };
+        assert_eq!(result, 7);
+
+        let ir = std::fs::read_to_string(output.with_extension("ll")).unwrap();
+        std::fs::remove_file(output.with_extension("ll")).unwrap();
+        assert!(ir.contains("!DIFile(filename: \"id.lir\""));
+        assert!(ir.contains("!DISubprogram(name: \"id\", linkageName: \"fun_id\""));
+        assert!(ir.contains("!DILocalVariable(name: \"x\""));
+        assert!(ir.contains("!DILocation(line: 6, column: 3"));
+    }
 }
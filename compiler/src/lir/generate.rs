@@ -0,0 +1,465 @@
+use super::*;
+
+/// How deeply terms are nested and switches are nested in each other.
+const DEPTH: usize = 3;
+
+/// Builds a random program from a sequence of choices.
+///
+/// Smaller choices make simpler programs, and running out of choices makes
+/// the simplest ones, so shrinking the choices shrinks the program. Programs
+/// are well-formed and free of undefined behaviour:
+///
+/// - functions only apply the functions defined after them, and are only
+///   passed partial applications of those, so evaluation always terminates
+/// - locals are used at most once unless they are copied, so no buffer is
+///   freed twice
+/// - switches have a case for every constructor, or a `_` case
+/// - partial applications passed to a function are only given the argument
+///   they are missing, and are never switched on
+pub fn generate(choices: &[u32]) -> Program {
+    let mut generator = Generator {
+        choices: choices.iter(),
+        ctors: vec![],
+        funs: vec![],
+        locals: 0,
+    };
+
+    // The first constructor has no arguments, so there is always a term to
+    // start from.
+    let ctors = 2 + generator.choose(3);
+    generator.ctors = (0..ctors)
+        .map(|i| match i {
+            0 => ("C0".to_string(), 0),
+            _ => (format!("C{}", i), generator.choose(3) as Arity),
+        })
+        .collect();
+    // A function can take a partial application of a function after it that
+    // only takes terms, so the last ones are chosen first.
+    let funs = generator.choose(4);
+    for i in (0..funs).rev() {
+        let partials = generator
+            .funs
+            .iter()
+            .any(|(_, params)| params.iter().all(|param| *param == Param::Term));
+        let params = (0..1 + generator.choose(2))
+            .map(|_| match generator.choose(4) {
+                3 if partials => Param::Partial { funs: i + 1 },
+                _ => Param::Term,
+            })
+            .collect();
+        generator.funs.insert(0, (format!("f{}", i), params));
+    }
+
+    let mut program = Program::new();
+    for (name, arity) in &generator.ctors {
+        program.insert(
+            name.clone(),
+            Global::Ctor {
+                arity: *arity,
+                symbol: None,
+                span: 0..0,
+            },
+        );
+    }
+    for (i, (name, params)) in generator.funs.clone().into_iter().enumerate() {
+        let arity = params.len() as Arity;
+        let block = generator.fun(i, &params);
+        program.insert(
+            name,
+            Global::Fun {
+                arity,
+                block,
+                span: 0..0,
+            },
+        );
+    }
+    let mut main = vec![];
+    generator.tail(&mut main, vec![], 0, true, DEPTH);
+    program.insert(
+        "main".to_string(),
+        Global::Fun {
+            arity: 0,
+            block: main,
+            span: 0..0,
+        },
+    );
+    program
+}
+
+struct Generator<'a> {
+    choices: std::slice::Iter<'a, u32>,
+    ctors: Vec<(Name, Arity)>,
+    funs: Vec<(Name, Vec<Param>)>,
+    /// How many locals have been named.
+    locals: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Param {
+    Term,
+    /// A partial application missing one argument, of one of the functions
+    /// from `funs` on that only take terms.
+    Partial {
+        funs: usize,
+    },
+}
+
+impl Generator<'_> {
+    /// Chooses a number below `n`, which is `0` once the choices run out.
+    fn choose(&mut self, n: usize) -> usize {
+        self.choices.next().map_or(0, |choice| *choice as usize % n)
+    }
+
+    fn local(&mut self) -> Name {
+        self.locals += 1;
+        format!("x{}", self.locals)
+    }
+
+    fn fun(&mut self, index: usize, params: &[Param]) -> Block {
+        let mut block = vec![];
+        let args = params
+            .iter()
+            .enumerate()
+            .map(|(index, param)| {
+                let name = self.local();
+                block.push(Op::LoadArg {
+                    name: name.clone(),
+                    var: "self".to_string(),
+                    index: index as Index,
+                    span: 0..0,
+                });
+                (name, *param)
+            })
+            .collect::<Vec<_>>();
+        block.push(Op::FreeArgs {
+            var: "self".to_string(),
+            span: 0..0,
+        });
+
+        // Partial applications are given their last argument before anything
+        // else, so they are never passed on to a function they could call.
+        let mut vars = vec![];
+        for (var, param) in args {
+            if param == Param::Term {
+                vars.push(var);
+                continue;
+            }
+            let arg = self.term(&mut block, &mut vars, index + 1, DEPTH);
+            let name = self.local();
+            block.push(Op::ApplyPartial {
+                name: name.clone(),
+                var,
+                args: vec![arg],
+                span: 0..0,
+            });
+            vars.push(name);
+        }
+        self.tail(&mut block, vars, index + 1, false, DEPTH);
+        block
+    }
+
+    /// Ends a block by returning a term, after switching on a local or
+    /// naming another term first. Only the functions from `funs` on can be
+    /// applied.
+    fn tail(
+        &mut self,
+        block: &mut Block,
+        mut vars: Vec<Name>,
+        funs: usize,
+        main: bool,
+        depth: usize,
+    ) {
+        match self.choose(3) {
+            1 if depth > 0 && !vars.is_empty() => {
+                let var = vars.remove(self.choose(vars.len()));
+                block.push(Op::Eval {
+                    var: var.clone(),
+                    span: 0..0,
+                });
+                // The constructors left without a case of their own share a
+                // `_` case.
+                let ctors = self
+                    .ctors
+                    .clone()
+                    .into_iter()
+                    .filter(|_| self.choose(4) != 3)
+                    .collect::<Vec<_>>();
+                let default = ctors.len() < self.ctors.len();
+                let mut cases = ctors
+                    .into_iter()
+                    .map(|(global, arity)| {
+                        let mut case = vec![];
+                        let mut vars = vars.clone();
+                        for index in 0..arity {
+                            let name = self.local();
+                            case.push(Op::LoadArg {
+                                name: name.clone(),
+                                var: var.clone(),
+                                index: index as Index,
+                                span: 0..0,
+                            });
+                            vars.push(name);
+                        }
+                        if arity > 0 {
+                            case.push(Op::FreeArgs {
+                                var: var.clone(),
+                                span: 0..0,
+                            });
+                        }
+                        self.tail(&mut case, vars, funs, main, depth - 1);
+                        Case {
+                            global,
+                            block: case,
+                            span: 0..0,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if default {
+                    let mut case = vec![];
+                    self.tail(&mut case, vars, funs, main, depth - 1);
+                    cases.push(Case {
+                        global: "_".to_string(),
+                        block: case,
+                        span: 0..0,
+                    });
+                }
+                block.push(Op::Switch {
+                    var,
+                    cases,
+                    span: 0..0,
+                });
+            }
+            2 if depth > 0 => {
+                let var = self.term(block, &mut vars, funs, depth);
+                vars.push(var);
+                self.tail(block, vars, funs, main, depth - 1);
+            }
+            _ => {
+                let var = self.term(block, &mut vars, funs, depth);
+                block.push(Op::Eval {
+                    var: var.clone(),
+                    span: 0..0,
+                });
+                block.push(if main {
+                    Op::ReturnSymbol { var, span: 0..0 }
+                } else {
+                    Op::Return { var, span: 0..0 }
+                });
+            }
+        }
+    }
+
+    /// Builds a term and names it, moving or copying locals from `vars`.
+    fn term(&mut self, block: &mut Block, vars: &mut Vec<Name>, funs: usize, depth: usize) -> Name {
+        let callable = self.funs.len() - funs;
+        match self.choose(5) {
+            1 if !vars.is_empty() => vars.remove(self.choose(vars.len())),
+            2 if !vars.is_empty() => {
+                let var = vars[self.choose(vars.len())].clone();
+                let name = self.local();
+                block.push(Op::Copy {
+                    name: name.clone(),
+                    var,
+                    span: 0..0,
+                });
+                name
+            }
+            3 if depth > 0 => {
+                let ctor = self.choose(self.ctors.len());
+                let (ctor, arity) = self.ctors[ctor].clone();
+                let params = vec![Param::Term; arity as usize];
+                self.apply(block, vars, ctor, &params, false, funs, depth)
+            }
+            4 if depth > 0 && callable > 0 => {
+                let fun = funs + self.choose(callable);
+                let (fun, params) = self.funs[fun].clone();
+                let partial = self.choose(2) == 1;
+                self.apply(block, vars, fun, &params, partial, funs, depth)
+            }
+            _ => self.apply(block, vars, "C0".to_string(), &[], false, funs, depth),
+        }
+    }
+
+    /// Applies a global to as many terms as it takes, all at once or as a
+    /// partial application that is applied to the rest in steps.
+    #[allow(clippy::too_many_arguments)]
+    fn apply(
+        &mut self,
+        block: &mut Block,
+        vars: &mut Vec<Name>,
+        global: Name,
+        params: &[Param],
+        partial: bool,
+        funs: usize,
+        depth: usize,
+    ) -> Name {
+        // The arguments come first, since they might load the same global,
+        // which would replace the local it is loaded into.
+        let args = params
+            .iter()
+            .map(|param| match *param {
+                Param::Term => self.term(block, vars, funs, depth - 1),
+                Param::Partial { funs: from } => self.partial(block, vars, from, funs, depth - 1),
+            })
+            .collect::<Vec<_>>();
+        block.push(Op::LoadGlobal {
+            global: global.clone(),
+            span: 0..0,
+        });
+
+        let name = self.local();
+        let arity = params.len();
+        if arity == 0 {
+            block.push(Op::Copy {
+                name: name.clone(),
+                var: global,
+                span: 0..0,
+            });
+            return name;
+        }
+        if !partial {
+            block.push(Op::NewApp {
+                name: name.clone(),
+                var: global,
+                args,
+                span: 0..0,
+            });
+            return name;
+        }
+
+        let (first, mut rest) = args.split_at(self.choose(arity));
+        block.push(Op::NewPartial {
+            name: name.clone(),
+            var: global,
+            args: first.to_vec(),
+            span: 0..0,
+        });
+        let mut name = name;
+        while !rest.is_empty() {
+            let (next, remaining) = rest.split_at(1 + self.choose(rest.len()));
+            let var = name;
+            name = self.local();
+            block.push(Op::ApplyPartial {
+                name: name.clone(),
+                var,
+                args: next.to_vec(),
+                span: 0..0,
+            });
+            rest = remaining;
+        }
+        name
+    }
+
+    /// Builds a partial application missing one argument, of one of the
+    /// functions from `from` on that only take terms. Its arguments are
+    /// built like those of any other term.
+    fn partial(
+        &mut self,
+        block: &mut Block,
+        vars: &mut Vec<Name>,
+        from: usize,
+        funs: usize,
+        depth: usize,
+    ) -> Name {
+        let candidates = (from..self.funs.len())
+            .filter(|i| self.funs[*i].1.iter().all(|param| *param == Param::Term))
+            .collect::<Vec<_>>();
+        let fun = candidates[self.choose(candidates.len())];
+        let (fun, params) = self.funs[fun].clone();
+        let args = params[1..]
+            .iter()
+            .map(|_| self.term(block, vars, funs, depth))
+            .collect::<Vec<_>>();
+        block.push(Op::LoadGlobal {
+            global: fun.clone(),
+            span: 0..0,
+        });
+
+        let name = self.local();
+        block.push(Op::NewPartial {
+            name: name.clone(),
+            var: fun,
+            args,
+            span: 0..0,
+        });
+        name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report;
+    use compile::{Config, Emit, Mode, OptLevel, Output};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_simplest() {
+        let expected = "\
+C0 = 0
+
+C1 = 0
+
+main = 0 {
+  load_global C0
+  x1 = copy C0
+  eval x1
+  return_symbol x1
+}
+";
+        assert_eq!(print::print(&generate(&[])), expected);
+    }
+
+    proptest! {
+        // Every case is compiled and linked several times, so there are
+        // fewer of them than usual.
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// Checks that the JIT, compiled executables and the interpreter agree
+        /// on random programs at every optimization level.
+        #[test]
+        fn test_differential(choices in proptest::collection::vec(any::<u32>(), 0..256)) {
+            let program = generate(&choices);
+            let source = print::print(&program);
+            prop_assert!(!report::has_errors(&validate::check(&program)), "{}", source);
+
+            let term = interp::interp(&program, &Mode::Print { depth: None });
+            let exit_code = interp::interp(&program, &Mode::Jit);
+            prop_assert!(term.is_ok() && exit_code.is_ok(), "{}", source);
+            let (term, exit_code) = (term.unwrap(), exit_code.unwrap());
+
+            let output = std::env::temp_dir()
+                .join(format!("yoke-differential-{}", std::process::id()));
+            for opt_level in OptLevel::ALL {
+                let config = Config {
+                    mode: Mode::Print { depth: None },
+                    opt_level,
+                    ..Config::default()
+                };
+                let jit = compile::compile(&program, config);
+                prop_assert_eq!(jit.as_ref(), Ok(&term), "{}", source);
+
+                let config = Config {
+                    opt_level,
+                    ..Config::default()
+                };
+                let jit = compile::compile(&program, config);
+                prop_assert_eq!(jit.as_ref(), Ok(&exit_code), "{}", source);
+
+                let config = Config {
+                    mode: Mode::Aot,
+                    opt_level,
+                    emit: vec![Emit::Exe],
+                    output: output.clone(),
+                    ..Config::default()
+                };
+                let aot = compile::compile(&program, config);
+                prop_assert_eq!(aot, Ok(Output::Binary), "{}", source);
+                let status = std::process::Command::new(&output).status().unwrap();
+                let _ = std::fs::remove_file(&output);
+                let aot = Output::ExitCode(status.code().unwrap());
+                prop_assert_eq!(&aot, &exit_code, "{}", source);
+            }
+        }
+    }
+}
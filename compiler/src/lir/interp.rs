@@ -0,0 +1,667 @@
+use super::{
+    compile::{Mode, Output, Value},
+    symbol::SymbolTable,
+    *,
+};
+use crate::report::{Diagnostic, Level};
+
+/// Evaluates a program without LLVM, as a reference for what compiled code
+/// should do.
+///
+/// Terms are modelled on those of the RTS: locals live on a stack like the
+/// allocas of compiled code, and argument buffers are allocated on a heap
+/// where they stay until they are freed. Anything that compiled code would do
+/// without checking, like using a freed buffer, is reported as an error.
+///
+/// `Mode::Print` reads back the result of `main` like the JIT does, and the
+/// other modes give its symbol as the exit code.
+pub fn interp(program: &Program, mode: &Mode) -> Result<Output, InterpError> {
+    let Some(Global::Fun { block, .. }) = program.get("main") else {
+        return Err(InterpError::Undefined {
+            message: "no main function".to_string(),
+            span: 0..0,
+        });
+    };
+
+    let (symbols, _) = symbol::intern(program);
+    let mut machine = Machine {
+        program,
+        symbols,
+        stack: Vec::new(),
+        heap: Vec::new(),
+    };
+    let mut frame = Frame {
+        fun: "main",
+        arg: None,
+        locals: vec![HashMap::new()],
+        scrutinees: Vec::new(),
+    };
+
+    let span = block.last().map_or(0..0, |op| op.span().clone());
+    let Flow::ReturnSymbol(term) = machine.block(block, &mut frame, &span)? else {
+        return Err(InterpError::Undefined {
+            message: "main returned a term instead of a symbol".to_string(),
+            span,
+        });
+    };
+    match mode {
+        Mode::Print { depth } => Ok(Output::Term(machine.read_back(term, *depth, &span)?)),
+        _ => Ok(Output::ExitCode(term.symbol as i32)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InterpError {
+    UnknownLocal {
+        name: Name,
+        span: Span,
+    },
+    UnknownGlobal {
+        name: Name,
+        span: Span,
+    },
+    NotCtor {
+        name: Name,
+        span: Span,
+    },
+    /// A `todo` was reached in a function, switching on a constructor if it
+    /// was in a switch.
+    Todo {
+        fun: Name,
+        ctor: Option<Name>,
+    },
+    /// Something that is undefined behaviour in compiled code.
+    Undefined {
+        message: String,
+        span: Span,
+    },
+}
+
+impl From<InterpError> for Diagnostic {
+    fn from(error: InterpError) -> Self {
+        match error {
+            InterpError::UnknownLocal { name, span } => {
+                Diagnostic::new(Level::Error, format!("no local with name {}", name))
+                    .with_label(span, format!("{} is not defined here", name))
+            }
+            InterpError::UnknownGlobal { name, span } => {
+                Diagnostic::new(Level::Error, format!("no global with name {}", name))
+                    .with_label(span, format!("{} is not defined", name))
+            }
+            InterpError::NotCtor { name, span } => {
+                Diagnostic::new(Level::Error, format!("{} is not a constructor", name))
+                    .with_label(span, "switch cases must be constructors")
+            }
+            InterpError::Todo { fun, ctor: None } => {
+                Diagnostic::new(Level::Error, format!("unhandled case in {}", fun))
+            }
+            InterpError::Todo {
+                fun,
+                ctor: Some(ctor),
+            } => Diagnostic::new(Level::Error, format!("unhandled case in {}: {}", fun, ctor)),
+            InterpError::Undefined { message, span } => {
+                Diagnostic::new(Level::Error, "undefined behaviour").with_label(span, message)
+            }
+        }
+    }
+}
+
+/// A term in the RTS. The function of a term is named rather than pointed to,
+/// and its arguments are a buffer on the heap.
+#[derive(Debug, Clone)]
+struct Term {
+    fun: Fun,
+    args: Option<usize>,
+    symbol: Symbol,
+    length: u16,
+    capacity: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Fun {
+    /// The function of a zeroed term, like the missing arguments of a partial
+    /// application.
+    Null,
+    Noop,
+    Global(Name),
+}
+
+impl Term {
+    const ZEROED: Term = Term {
+        fun: Fun::Null,
+        args: None,
+        symbol: 0,
+        length: 0,
+        capacity: 0,
+    };
+}
+
+struct Machine<'a> {
+    program: &'a Program,
+    symbols: SymbolTable,
+    /// The locals of every function being evaluated.
+    stack: Vec<Term>,
+    /// Every buffer that has been allocated, which is `None` once it is freed.
+    heap: Vec<Option<Vec<Term>>>,
+}
+
+/// A function being evaluated.
+struct Frame<'a> {
+    fun: &'a str,
+    /// Where the term being evaluated is on the stack, which is `self`.
+    arg: Option<usize>,
+    locals: Vec<HashMap<Name, usize>>,
+    /// The terms switched on by the switches around the current block.
+    scrutinees: Vec<usize>,
+}
+
+enum Flow {
+    Return,
+    ReturnSymbol(Term),
+}
+
+impl Frame<'_> {
+    fn lookup(&self, var: &str, span: &Span) -> Result<usize, InterpError> {
+        for scope in self.locals.iter().rev() {
+            if let Some(local) = scope.get(var) {
+                return Ok(*local);
+            }
+        }
+        Err(InterpError::UnknownLocal {
+            name: var.to_string(),
+            span: span.clone(),
+        })
+    }
+
+    fn define(&mut self, name: &str, local: usize) {
+        self.locals
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), local);
+    }
+}
+
+impl Machine<'_> {
+    /// Evaluates a block up to its terminator. `span` is used when the block
+    /// doesn't have one.
+    fn block(
+        &mut self,
+        block: &Block,
+        frame: &mut Frame,
+        span: &Span,
+    ) -> Result<Flow, InterpError> {
+        for op in block {
+            if let Some(flow) = self.op(op, frame)? {
+                return Ok(flow);
+            }
+        }
+        Err(undefined("block has no terminator", span))
+    }
+
+    fn op(&mut self, op: &Op, frame: &mut Frame) -> Result<Option<Flow>, InterpError> {
+        match op {
+            Op::LoadGlobal { global, span } => {
+                let term = self.global(global, span)?;
+                let local = self.push(term);
+                frame.define(global, local);
+            }
+            Op::LoadArg {
+                name,
+                var,
+                index,
+                span,
+            } => {
+                let term = &self.stack[frame.lookup(var, span)?];
+                let args = self.buffer(term, span)?;
+                let Some(arg) = args.get(*index as usize).cloned() else {
+                    return Err(undefined(
+                        format!("{} has no argument {}", var, index),
+                        span,
+                    ));
+                };
+                let local = self.push(arg);
+                frame.define(name, local);
+            }
+            Op::NewApp {
+                name,
+                var,
+                args,
+                span,
+            } => {
+                let (local, args) = self.apply_args(var, args, frame, span)?;
+                let buffer = self.alloc(args);
+                self.stack[local].args = Some(buffer);
+                frame.define(name, local);
+            }
+            Op::NewPartial {
+                name,
+                var,
+                args,
+                span,
+            } => {
+                let (local, args) = self.apply_args(var, args, frame, span)?;
+                let term = &self.stack[local];
+                if args.len() >= term.capacity as usize {
+                    return Err(undefined(
+                        "partial application has no room for its function",
+                        span,
+                    ));
+                }
+                let fun = Term {
+                    fun: term.fun.clone(),
+                    args: None,
+                    symbol: term.symbol,
+                    length: 0,
+                    capacity: 0,
+                };
+                let length = args.len() as u16;
+                let mut buffer = args;
+                buffer.resize(term.capacity as usize - 1, Term::ZEROED);
+                buffer.push(fun);
+                let buffer = self.alloc(buffer);
+
+                let term = &mut self.stack[local];
+                term.args = Some(buffer);
+                term.fun = Fun::Noop;
+                term.length = length;
+                frame.define(name, local);
+            }
+            Op::ApplyPartial {
+                name,
+                var,
+                args,
+                span,
+            } => {
+                let (local, args) = self.apply_args(var, args, frame, span)?;
+                let term = self.stack[local].clone();
+                let length = term.length as usize + args.len();
+                if term.capacity == 0 || length > term.capacity as usize {
+                    return Err(undefined(
+                        "too many arguments for partial application",
+                        span,
+                    ));
+                }
+                let buffer = self.buffer_mut(&term, span)?;
+                let fun = buffer[term.capacity as usize - 1].fun.clone();
+                for (i, arg) in args.into_iter().enumerate() {
+                    buffer[term.length as usize + i] = arg;
+                }
+
+                let term = &mut self.stack[local];
+                term.length = length as u16;
+                if term.length == term.capacity {
+                    term.fun = fun;
+                }
+                frame.define(name, local);
+            }
+            Op::Copy { name, var, span } => {
+                let term = self.stack[frame.lookup(var, span)?].clone();
+                let copy = self.copy(&term, span)?;
+                let local = self.push(copy);
+                frame.define(name, local);
+            }
+            Op::Eval { var, span } => {
+                let local = frame.lookup(var, span)?;
+                self.eval(local, span)?;
+            }
+            Op::FreeArgs { var, span } => {
+                let term = self.stack[frame.lookup(var, span)?].clone();
+                self.free_args(&term, span)?;
+            }
+            Op::FreeTerm { var, span } => {
+                let term = self.stack[frame.lookup(var, span)?].clone();
+                self.free_term(&term, span)?;
+            }
+            Op::ReturnSymbol { var, span } => {
+                if frame.arg.is_some() {
+                    return Err(undefined("return_symbol outside of main", span));
+                }
+                let term = self.stack[frame.lookup(var, span)?].clone();
+                return Ok(Some(Flow::ReturnSymbol(term)));
+            }
+            Op::Return { var, span } => {
+                let Some(arg) = frame.arg else {
+                    return Err(undefined("return in main", span));
+                };
+                self.stack[arg] = self.stack[frame.lookup(var, span)?].clone();
+                return Ok(Some(Flow::Return));
+            }
+            Op::Switch { var, cases, span } => {
+                let local = frame.lookup(var, span)?;
+                let symbol = self.stack[local].symbol;
+                for case in cases {
+                    if !case.is_default() && self.case_symbol(case)? != symbol {
+                        continue;
+                    }
+                    frame.scrutinees.push(local);
+                    frame.locals.push(HashMap::new());
+                    let flow = self.block(&case.block, frame, &case.span)?;
+                    frame.locals.pop();
+                    frame.scrutinees.pop();
+                    return Ok(Some(flow));
+                }
+                // Like `todo` in the case that's missing.
+                return Err(InterpError::Todo {
+                    fun: frame.fun.to_string(),
+                    ctor: self.symbols.name(symbol).map(str::to_string),
+                });
+            }
+            Op::Todo { .. } => {
+                let ctor = frame
+                    .scrutinees
+                    .last()
+                    .and_then(|local| self.symbols.name(self.stack[*local].symbol))
+                    .map(str::to_string);
+                return Err(InterpError::Todo {
+                    fun: frame.fun.to_string(),
+                    ctor,
+                });
+            }
+        }
+        Ok(None)
+    }
+
+    /// Calls the function of a term on the stack, which replaces it with its
+    /// result.
+    fn eval(&mut self, local: usize, span: &Span) -> Result<(), InterpError> {
+        let name = match &self.stack[local].fun {
+            Fun::Null => return Err(undefined("evaluated a zeroed term", span)),
+            Fun::Noop => return Ok(()),
+            Fun::Global(name) => name.clone(),
+        };
+        let Some(Global::Fun { block, .. }) = self.program.get(&name) else {
+            return Err(InterpError::UnknownGlobal {
+                name,
+                span: span.clone(),
+            });
+        };
+
+        let mut frame = Frame {
+            fun: &name,
+            arg: Some(local),
+            locals: vec![HashMap::from([("self".to_string(), local)])],
+            scrutinees: Vec::new(),
+        };
+        let base = self.stack.len();
+        let flow = self.block(block, &mut frame, span)?;
+        self.stack.truncate(base);
+        match flow {
+            Flow::Return => Ok(()),
+            Flow::ReturnSymbol(_) => unreachable!(),
+        }
+    }
+
+    /// Evaluates the arguments of an evaluated term, down to `depth`, and
+    /// reads it back like the JIT does.
+    fn read_back(
+        &mut self,
+        term: Term,
+        depth: Option<usize>,
+        span: &Span,
+    ) -> Result<Value, InterpError> {
+        if term.symbol == 0 {
+            return Ok(Value::Fun);
+        }
+
+        let name = match self.symbols.name(term.symbol) {
+            Some(name) => name.to_string(),
+            None => term.symbol.to_string(),
+        };
+        let args = (0..term.length as usize)
+            .map(|i| match depth {
+                Some(0) => Ok(Value::Elided),
+                _ => {
+                    let arg = self.buffer(&term, span)?[i].clone();
+                    let local = self.push(arg);
+                    self.eval(local, span)?;
+                    let arg = self.stack.pop().unwrap();
+                    self.buffer_mut(&term, span)?[i] = arg.clone();
+                    self.read_back(arg, depth.map(|depth| depth - 1), span)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Value::Ctor(name, args))
+    }
+
+    /// Finds the local being applied and the terms it is applied to.
+    fn apply_args(
+        &self,
+        var: &str,
+        args: &[Name],
+        frame: &Frame,
+        span: &Span,
+    ) -> Result<(usize, Vec<Term>), InterpError> {
+        let local = frame.lookup(var, span)?;
+        let args = args
+            .iter()
+            .map(|arg| Ok(self.stack[frame.lookup(arg, span)?].clone()))
+            .collect::<Result<_, _>>()?;
+        Ok((local, args))
+    }
+
+    fn global(&self, name: &str, span: &Span) -> Result<Term, InterpError> {
+        match self.program.get(name) {
+            Some(Global::Ctor { arity, .. }) => Ok(Term {
+                fun: Fun::Noop,
+                args: None,
+                symbol: self.symbols.symbol(name).unwrap(),
+                length: *arity,
+                capacity: *arity,
+            }),
+            Some(Global::Fun { arity, .. }) => Ok(Term {
+                fun: Fun::Global(name.to_string()),
+                args: None,
+                symbol: 0,
+                length: *arity,
+                capacity: *arity,
+            }),
+            None => Err(InterpError::UnknownGlobal {
+                name: name.to_string(),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    fn case_symbol(&self, case: &Case) -> Result<Symbol, InterpError> {
+        match self.program.get(&case.global) {
+            Some(Global::Ctor { .. }) => Ok(self.symbols.symbol(&case.global).unwrap()),
+            Some(Global::Fun { .. }) => Err(InterpError::NotCtor {
+                name: case.global.clone(),
+                span: case.span.clone(),
+            }),
+            None => Err(InterpError::UnknownGlobal {
+                name: case.global.clone(),
+                span: case.span.clone(),
+            }),
+        }
+    }
+
+    /// Like `copy` in the RTS, copies a term and every buffer it points to.
+    fn copy(&mut self, term: &Term, span: &Span) -> Result<Term, InterpError> {
+        let mut copy = term.clone();
+        if term.capacity == 0 {
+            return Ok(copy);
+        }
+
+        let args = self.buffer(term, span)?.clone();
+        if args.len() < term.capacity as usize {
+            return Err(undefined("copied past the end of a buffer", span));
+        }
+        let args = args[..term.capacity as usize]
+            .iter()
+            .map(|arg| self.copy(arg, span))
+            .collect::<Result<_, _>>()?;
+        copy.args = Some(self.alloc(args));
+        Ok(copy)
+    }
+
+    fn free_args(&mut self, term: &Term, span: &Span) -> Result<(), InterpError> {
+        let Some(buffer) = term.args else {
+            return Ok(());
+        };
+        match self.heap[buffer].take() {
+            Some(_) => Ok(()),
+            None => Err(undefined("freed a buffer that was already freed", span)),
+        }
+    }
+
+    fn free_term(&mut self, term: &Term, span: &Span) -> Result<(), InterpError> {
+        for i in 0..term.length as usize {
+            let arg = self.buffer(term, span)?[i].clone();
+            self.free_term(&arg, span)?;
+        }
+        self.free_args(term, span)
+    }
+
+    fn push(&mut self, term: Term) -> usize {
+        self.stack.push(term);
+        self.stack.len() - 1
+    }
+
+    fn alloc(&mut self, args: Vec<Term>) -> usize {
+        self.heap.push(Some(args));
+        self.heap.len() - 1
+    }
+
+    /// The arguments of a term, which must not have been freed.
+    fn buffer(&self, term: &Term, span: &Span) -> Result<&Vec<Term>, InterpError> {
+        match term.args.map(|buffer| &self.heap[buffer]) {
+            Some(Some(args)) => Ok(args),
+            Some(None) => Err(undefined("used a buffer after it was freed", span)),
+            None => Err(undefined("used the arguments of a term without any", span)),
+        }
+    }
+
+    fn buffer_mut(&mut self, term: &Term, span: &Span) -> Result<&mut Vec<Term>, InterpError> {
+        match term.args.map(|buffer| &mut self.heap[buffer]) {
+            Some(Some(args)) => Ok(args),
+            Some(None) => Err(undefined("used a buffer after it was freed", span)),
+            None => Err(undefined("used the arguments of a term without any", span)),
+        }
+    }
+}
+
+fn undefined(message: impl Into<String>, span: &Span) -> InterpError {
+    InterpError::Undefined {
+        message: message.into(),
+        span: span.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mir;
+
+    fn programs() -> Vec<Program> {
+        let mut programs = vec![
+            parse::parse(
+                "True = 0 7
+                 main = 0 { load_global id load_global True x = new_app id { True } eval x return_symbol x }
+                 id = 1 { x = load_arg self 0 free_args self eval x return x }",
+            )
+            .unwrap(),
+            parse::parse(
+                "True = 0 Nil = 0 Cons = 2 Just = 1
+                 main = 0 {
+                   load_global id load_global True t = new_app id { True }
+                   load_global Cons load_global Nil l = new_app Cons { t Nil }
+                   load_global Just j = new_app Just { l }
+                   c = copy j
+                   free_term j
+                   return_symbol c
+                 }
+                 id = 1 { x = load_arg self 0 free_args self eval x return x }",
+            )
+            .unwrap(),
+        ];
+        let lir = std::fs::read_to_string("../examples/map-not-maybe-bool.lir").unwrap();
+        programs.push(parse::parse(&lir).unwrap());
+        let mir = std::fs::read_to_string("../examples/map-not-maybe-bool.mir").unwrap();
+        programs.push(mir::compile::compile(mir::parse::parse(&mir).unwrap()));
+        programs
+    }
+
+    #[test]
+    fn test_agrees_with_jit() {
+        let mode = |print| match print {
+            Some(depth) => Mode::Print { depth },
+            None => Mode::Jit,
+        };
+        for program in programs() {
+            for print in [None, Some(None), Some(Some(1))] {
+                let config = compile::Config {
+                    mode: mode(print),
+                    ..compile::Config::default()
+                };
+                let expected = compile::compile(&program, config).unwrap();
+                assert_eq!(interp(&program, &mode(print)), Ok(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_todo() {
+        let program = parse::parse(
+            "Nothing = 0 Just = 1
+             main = 0 { load_global unwrap load_global Nothing x = new_app unwrap { Nothing } eval x return_symbol x }
+             unwrap = 1 { x = load_arg self 0 free_args self eval x switch x { Just { todo } Nothing { todo } } }",
+        )
+        .unwrap();
+        assert_eq!(
+            interp(&program, &Mode::Jit),
+            Err(InterpError::Todo {
+                fun: "unwrap".to_string(),
+                ctor: Some("Nothing".to_string()),
+            })
+        );
+
+        // A switch without a case for the symbol acts like `todo`.
+        let program = parse::parse(
+            "Nothing = 0 Just = 1
+             main = 0 { load_global unwrap load_global Nothing x = new_app unwrap { Nothing } eval x return_symbol x }
+             unwrap = 1 { x = load_arg self 0 free_args self eval x switch x { Just { todo } } }",
+        )
+        .unwrap();
+        assert_eq!(
+            interp(&program, &Mode::Jit),
+            Err(InterpError::Todo {
+                fun: "unwrap".to_string(),
+                ctor: Some("Nothing".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_undefined() {
+        let error = |input: &str| {
+            let Err(InterpError::Undefined { message, .. }) =
+                interp(&parse::parse(input).unwrap(), &Mode::Jit)
+            else {
+                panic!()
+            };
+            message
+        };
+
+        assert_eq!(
+            error(
+                "True = 0 Just = 1
+                 main = 0 { load_global Just load_global True j = new_app Just { True } free_args j free_args j return_symbol j }"
+            ),
+            "freed a buffer that was already freed"
+        );
+
+        assert_eq!(
+            error(
+                "True = 0 Just = 1
+                 main = 0 { load_global Just load_global True j = new_app Just { True } free_args j x = load_arg j 0 return_symbol x }"
+            ),
+            "used a buffer after it was freed"
+        );
+
+        assert_eq!(
+            error("Just = 1 main = 0 { load_global Just x = load_arg Just 0 return_symbol x }"),
+            "used the arguments of a term without any"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::lex::{is_name, Comment, LexError, Scanner};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tok<'input> {
+    Equals,
+    LBrace,
+    RBrace,
+    LoadGlobal,
+    LoadArg,
+    NewApp,
+    NewPartial,
+    ApplyPartial,
+    Copy,
+    Eval,
+    FreeArgs,
+    FreeTerm,
+    ReturnSymbol,
+    Return,
+    Switch,
+    Todo,
+    Name(&'input str),
+    Number(&'input str),
+}
+
+const KEYWORDS: &[(&str, Tok)] = &[
+    ("load_global", Tok::LoadGlobal),
+    ("load_arg", Tok::LoadArg),
+    ("new_app", Tok::NewApp),
+    ("new_partial", Tok::NewPartial),
+    ("apply_partial", Tok::ApplyPartial),
+    ("copy", Tok::Copy),
+    ("eval", Tok::Eval),
+    ("free_args", Tok::FreeArgs),
+    ("free_term", Tok::FreeTerm),
+    ("return_symbol", Tok::ReturnSymbol),
+    ("return", Tok::Return),
+    ("switch", Tok::Switch),
+    ("todo", Tok::Todo),
+];
+
+impl Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Equals => write!(f, "="),
+            Tok::LBrace => write!(f, "{{"),
+            Tok::RBrace => write!(f, "}}"),
+            Tok::Name(text) | Tok::Number(text) => write!(f, "{}", text),
+            tok => {
+                let (keyword, _) = KEYWORDS.iter().find(|(_, t)| t == tok).unwrap();
+                write!(f, "{}", keyword)
+            }
+        }
+    }
+}
+
+pub struct Lexer<'input> {
+    scanner: Scanner<'input>,
+    open: Vec<usize>,
+    /// The blocks that have been closed, from where they open to where they
+    /// close.
+    pub blocks: BTreeMap<usize, usize>,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(input: &'input str) -> Self {
+        Self {
+            scanner: Scanner::new(input),
+            open: vec![],
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    pub fn comments(self) -> Vec<Comment> {
+        self.scanner.comments
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Result<(usize, Tok<'input>, usize), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = match self.scanner.next_token() {
+            Ok(c) => c?,
+            Err(error) => return Some(Err(error)),
+        };
+        let (start, text, end) = match c {
+            '=' | '{' | '}' => self.scanner.take(1),
+            c if c.is_ascii_digit() => self.scanner.take_while(|c| c.is_ascii_digit()),
+            c if c.is_ascii_alphabetic() || c == '_' => self.scanner.take_while(is_name),
+            _ => return Some(Err(self.scanner.invalid())),
+        };
+        let tok = match text {
+            "=" => Tok::Equals,
+            "{" => {
+                self.open.push(start);
+                Tok::LBrace
+            }
+            "}" => {
+                if let Some(open) = self.open.pop() {
+                    self.blocks.insert(open, start);
+                }
+                Tok::RBrace
+            }
+            _ if c.is_ascii_digit() => Tok::Number(text),
+            _ => KEYWORDS
+                .iter()
+                .find(|(keyword, _)| *keyword == text)
+                .map_or(Tok::Name(text), |(_, tok)| *tok),
+        };
+        Some(Ok((start, tok, end)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lexer() {
+        let mut lexer = Lexer::new("f = 1 { -- f\n  x = load_arg self 0 return x }");
+        let toks = lexer
+            .by_ref()
+            .map(|result| result.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            toks,
+            vec![
+                Tok::Name("f"),
+                Tok::Equals,
+                Tok::Number("1"),
+                Tok::LBrace,
+                Tok::Name("x"),
+                Tok::Equals,
+                Tok::LoadArg,
+                Tok::Name("self"),
+                Tok::Number("0"),
+                Tok::Return,
+                Tok::Name("x"),
+                Tok::RBrace,
+            ]
+        );
+        assert_eq!(lexer.blocks, BTreeMap::from([(6, 44)]));
+        assert_eq!(
+            lexer.comments(),
+            vec![Comment {
+                span: 8..12,
+                trailing: true,
+            }]
+        );
+
+        let mut lexer = Lexer::new("x = copy $");
+        assert_eq!(
+            lexer.nth(3),
+            Some(Err(LexError::InvalidToken { location: 9 }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+}
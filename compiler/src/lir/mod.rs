@@ -1,6 +1,16 @@
 pub mod compile;
+#[cfg(test)]
+pub mod generate;
+pub mod interp;
+pub mod lex;
+pub mod ownership;
 pub mod parse;
+pub mod print;
+pub mod symbol;
+pub mod validate;
 
+pub use crate::report::Span;
+pub use indexmap::IndexMap;
 pub use std::collections::HashMap;
 
 pub type Name = String;
@@ -11,12 +21,21 @@ pub type Arity = u16;
 
 pub type Index = u64;
 
-pub type Program = HashMap<String, Global>;
+/// The globals of a program, in the order they are defined.
+pub type Program = IndexMap<String, Global>;
 
 #[derive(Debug, PartialEq)]
 pub enum Global {
-    Ctor { arity: Arity, symbol: Symbol },
-    Fun { arity: Arity, block: Block },
+    Ctor {
+        arity: Arity,
+        symbol: Option<Symbol>,
+        span: Span,
+    },
+    Fun {
+        arity: Arity,
+        block: Block,
+        span: Span,
+    },
 }
 
 pub type Block = Vec<Op>;
@@ -25,55 +44,106 @@ pub type Block = Vec<Op>;
 pub enum Op {
     LoadGlobal {
         global: Name,
+        span: Span,
     },
     LoadArg {
         name: Name,
         var: Name,
         index: Index,
+        span: Span,
     },
     NewApp {
         name: Name,
         var: Name,
         args: Vec<Name>,
+        span: Span,
     },
     NewPartial {
         name: Name,
         var: Name,
         args: Vec<Name>,
+        span: Span,
     },
     ApplyPartial {
         name: Name,
         var: Name,
         args: Vec<Name>,
+        span: Span,
     },
     Copy {
         name: Name,
         var: Name,
+        span: Span,
     },
     Eval {
         var: Name,
+        span: Span,
     },
     FreeArgs {
         var: Name,
+        span: Span,
     },
     FreeTerm {
         var: Name,
+        span: Span,
     },
     ReturnSymbol {
         var: Name,
+        span: Span,
     },
     Return {
         var: Name,
+        span: Span,
     },
     Switch {
         var: Name,
         cases: Vec<Case>,
+        span: Span,
+    },
+    Todo {
+        span: Span,
     },
-    Todo,
+}
+
+impl Global {
+    pub fn span(&self) -> &Span {
+        match self {
+            Global::Ctor { span, .. } | Global::Fun { span, .. } => span,
+        }
+    }
+}
+
+impl Op {
+    pub fn span(&self) -> &Span {
+        match self {
+            Op::LoadGlobal { span, .. }
+            | Op::LoadArg { span, .. }
+            | Op::NewApp { span, .. }
+            | Op::NewPartial { span, .. }
+            | Op::ApplyPartial { span, .. }
+            | Op::Copy { span, .. }
+            | Op::Eval { span, .. }
+            | Op::FreeArgs { span, .. }
+            | Op::FreeTerm { span, .. }
+            | Op::ReturnSymbol { span, .. }
+            | Op::Return { span, .. }
+            | Op::Switch { span, .. }
+            | Op::Todo { span } => span,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Case {
     pub global: Name,
     pub block: Block,
+    pub span: Span,
+}
+
+impl Case {
+    /// Whether this is the `_` case, which is taken for every constructor that
+    /// doesn't have a case of its own.
+    pub fn is_default(&self) -> bool {
+        self.global == "_"
+    }
 }
@@ -0,0 +1,402 @@
+use super::*;
+use crate::report::{Diagnostic, Level};
+use std::collections::HashSet;
+
+/// Warns about use-after-free, double frees and leaked allocations.
+///
+/// Every local refers to the `args` buffer of its term, if it has one. The
+/// buffers allocated by `new_app`, `new_partial` and `copy` are owned by the
+/// function until they are freed, returned, evaluated or moved into the
+/// arguments of another term. Copying a global of arity 0 allocates nothing,
+/// since it has no arguments. `self` and the arguments loaded from a buffer
+/// can be freed too, but they aren't reported as leaks since the caller may
+/// still own them. Each path through a switch is checked separately.
+///
+/// `eval` passes a term's buffer to its function, which may free it, so the
+/// result of evaluating a term isn't tracked.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut names = program.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let mut diagnostics = vec![];
+    for name in names {
+        let Global::Fun { block, span, .. } = &program[name] else {
+            continue;
+        };
+        let mut state = State::default();
+        if name != "main" {
+            let buffer = state.alloc(span.clone(), None, false);
+            state.locals.insert("self".to_string(), Some(buffer));
+        }
+        let mut checker = Checker {
+            program,
+            diagnostics: &mut diagnostics,
+        };
+        checker.block(block, state);
+    }
+    diagnostics
+}
+
+#[derive(Clone, Default)]
+struct State {
+    buffers: Vec<Buffer>,
+    locals: HashMap<Name, Option<usize>>,
+    /// The locals that are known to have no arguments, which `copy` doesn't
+    /// allocate a buffer for.
+    empty: HashSet<Name>,
+}
+
+#[derive(Clone)]
+struct Buffer {
+    /// Where the buffer was allocated or loaded.
+    span: Span,
+    /// The buffer containing this one, which frees it with `free_term`.
+    parent: Option<usize>,
+    /// Whether the buffer was allocated by this function.
+    owned: bool,
+    status: Status,
+}
+
+#[derive(Clone, PartialEq)]
+enum Status {
+    Live,
+    Freed(Span),
+    Moved,
+}
+
+impl State {
+    fn alloc(&mut self, span: Span, parent: Option<usize>, owned: bool) -> usize {
+        self.buffers.push(Buffer {
+            span,
+            parent,
+            owned,
+            status: Status::Live,
+        });
+        self.buffers.len() - 1
+    }
+
+    fn define(&mut self, name: &str, buffer: Option<usize>) {
+        self.locals.insert(name.to_string(), buffer);
+        self.empty.remove(name);
+    }
+
+    fn buffer(&self, var: &str) -> Option<usize> {
+        self.locals.get(var).copied().flatten()
+    }
+
+    /// The buffer and every buffer inside it.
+    fn tree(&self, buffer: usize) -> Vec<usize> {
+        let mut tree = vec![buffer];
+        let mut i = 0;
+        while i < tree.len() {
+            let parent = tree[i];
+            tree.extend(
+                (0..self.buffers.len()).filter(|&child| self.buffers[child].parent == Some(parent)),
+            );
+            i += 1;
+        }
+        tree
+    }
+}
+
+struct Checker<'a> {
+    program: &'a Program,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn block(&mut self, block: &Block, mut state: State) {
+        for op in block {
+            match op {
+                Op::LoadGlobal { global, .. } => {
+                    state.define(global, None);
+                    let arity = self.program.get(global).map(|global| match global {
+                        Global::Ctor { arity, .. } | Global::Fun { arity, .. } => *arity,
+                    });
+                    if arity == Some(0) {
+                        state.empty.insert(global.clone());
+                    }
+                }
+                Op::LoadArg {
+                    name, var, span, ..
+                } => {
+                    self.use_local(var, span, &state);
+                    let parent = state.buffer(var);
+                    let buffer = state.alloc(span.clone(), parent, false);
+                    state.define(name, Some(buffer));
+                }
+                Op::NewApp {
+                    name,
+                    var,
+                    args,
+                    span,
+                }
+                | Op::NewPartial {
+                    name,
+                    var,
+                    args,
+                    span,
+                } => {
+                    self.use_local(var, span, &state);
+                    let buffer = state.alloc(span.clone(), None, true);
+                    self.move_args(args, buffer, span, &mut state);
+                    state.define(var, Some(buffer));
+                    state.define(name, Some(buffer));
+                }
+                Op::ApplyPartial {
+                    name,
+                    var,
+                    args,
+                    span,
+                } => {
+                    self.use_local(var, span, &state);
+                    let buffer = state.buffer(var);
+                    if let Some(buffer) = buffer {
+                        self.move_args(args, buffer, span, &mut state);
+                    } else {
+                        args.iter()
+                            .for_each(|arg| self.use_local(arg, span, &state));
+                    }
+                    state.define(name, buffer);
+                }
+                Op::Copy { name, var, span } => {
+                    self.use_local(var, span, &state);
+                    if state.empty.contains(var) {
+                        state.define(name, None);
+                        state.empty.insert(name.clone());
+                    } else {
+                        let buffer = state.alloc(span.clone(), None, true);
+                        state.define(name, Some(buffer));
+                    }
+                }
+                Op::Eval { var, span } => {
+                    self.use_local(var, span, &state);
+                    if let Some(buffer) = state.buffer(var) {
+                        state.buffers[buffer].status = Status::Moved;
+                    }
+                    state.define(var, None);
+                }
+                Op::FreeArgs { var, span } => {
+                    if let Some(buffer) = state.buffer(var) {
+                        self.free(buffer, span, &mut state);
+                        for child in 0..state.buffers.len() {
+                            if state.buffers[child].parent == Some(buffer) {
+                                state.buffers[child].parent = None;
+                            }
+                        }
+                    }
+                }
+                Op::FreeTerm { var, span } => {
+                    if let Some(buffer) = state.buffer(var) {
+                        for buffer in state.tree(buffer) {
+                            self.free(buffer, span, &mut state);
+                        }
+                    }
+                }
+                Op::ReturnSymbol { var, span } => {
+                    self.use_local(var, span, &state);
+                    self.leaks(&state);
+                    return;
+                }
+                Op::Return { var, span } => {
+                    self.use_local(var, span, &state);
+                    if let Some(buffer) = state.buffer(var) {
+                        for buffer in state.tree(buffer) {
+                            state.buffers[buffer].status = Status::Moved;
+                        }
+                    }
+                    self.leaks(&state);
+                    return;
+                }
+                Op::Switch { var, cases, span } => {
+                    self.use_local(var, span, &state);
+                    for case in cases {
+                        self.block(&case.block, state.clone());
+                    }
+                    return;
+                }
+                Op::Todo { .. } => return,
+            }
+        }
+    }
+
+    /// Moves arguments into a buffer, so that they are freed along with it.
+    fn move_args(&mut self, args: &[Name], buffer: usize, span: &Span, state: &mut State) {
+        for arg in args {
+            self.use_local(arg, span, state);
+            let Some(arg) = state.buffer(arg) else {
+                continue;
+            };
+            if arg != buffer && !state.tree(arg).contains(&buffer) {
+                state.buffers[arg].parent = Some(buffer);
+            }
+        }
+    }
+
+    fn use_local(&mut self, var: &str, span: &Span, state: &State) {
+        let Some(buffer) = state.buffer(var) else {
+            return;
+        };
+        if let Status::Freed(freed) = &state.buffers[buffer].status {
+            self.warn(
+                Diagnostic::new(Level::Warning, format!("use of {} after it was freed", var))
+                    .with_label(span.clone(), "used here")
+                    .with_label(freed.clone(), "freed here"),
+            );
+        }
+    }
+
+    fn free(&mut self, buffer: usize, span: &Span, state: &mut State) {
+        match &state.buffers[buffer].status {
+            Status::Freed(freed) => self.warn(
+                Diagnostic::new(Level::Warning, "buffer is freed twice")
+                    .with_label(span.clone(), "freed again here")
+                    .with_label(freed.clone(), "first freed here"),
+            ),
+            _ => state.buffers[buffer].status = Status::Freed(span.clone()),
+        }
+    }
+
+    /// Reports buffers allocated by this function that are still live at the
+    /// end of a path and aren't inside another buffer.
+    fn leaks(&mut self, state: &State) {
+        for buffer in &state.buffers {
+            if buffer.owned && buffer.parent.is_none() && buffer.status == Status::Live {
+                self.warn(
+                    Diagnostic::new(Level::Warning, "allocation is never freed")
+                        .with_label(buffer.span.clone(), "allocated here"),
+                );
+            }
+        }
+    }
+
+    /// Paths through a switch share their beginning, so the same problem can
+    /// be found more than once.
+    fn warn(&mut self, diagnostic: Diagnostic) {
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(input: &str) -> Vec<String> {
+        check(&parse::parse(input).unwrap())
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_use_after_free() {
+        assert_eq!(
+            check(
+                &parse::parse(
+                    "Just = 1 main = 0 { load_global Just x = copy Just free_term x return_symbol x }"
+                )
+                .unwrap()
+            ),
+            vec![Diagnostic::new(Level::Warning, "use of x after it was freed")
+                .with_label(63..78, "used here")
+                .with_label(51..62, "freed here")]
+        );
+
+        assert_eq!(
+            messages(
+                "f = 1 {
+                   x = load_arg self 0
+                   free_term self
+                   eval x
+                   return x
+                 }"
+            ),
+            vec!["use of x after it was freed"]
+        );
+    }
+
+    #[test]
+    fn test_double_free() {
+        assert_eq!(
+            messages(
+                "True = 0 1 Just = 1
+                 main = 0 {
+                   load_global Just
+                   x = copy Just
+                   y = new_app Just { x }
+                   free_term y
+                   free_term x
+                   load_global True
+                   return_symbol True
+                 }"
+            ),
+            vec!["buffer is freed twice"]
+        );
+    }
+
+    #[test]
+    fn test_leak() {
+        assert_eq!(
+            messages(
+                "True = 0 1
+                 False = 0 2
+                 Just = 1
+                 main = 0 {
+                   load_global True
+                   load_global Just
+                   x = copy Just
+                   switch True {
+                     True { free_term x return_symbol True }
+                     False { return_symbol True }
+                   }
+                 }
+                 f = 1 {
+                   load_global f
+                   load_global Just
+                   x = copy Just
+                   y = new_app f { x }
+                   free_args y
+                   return self
+                 }"
+            ),
+            vec!["allocation is never freed", "allocation is never freed"]
+        );
+    }
+
+    #[test]
+    fn test_no_warnings() {
+        assert_eq!(
+            messages(
+                "True = 0 1
+                 main = 0 {
+                   load_global id
+                   load_global True
+                   result = new_app id { True }
+                   eval result
+                   return_symbol result
+                 }
+                 id = 1 {
+                   x = load_arg self 0
+                   free_args self
+                   eval x
+                   return x
+                 }"
+            ),
+            Vec::<String>::new()
+        );
+
+        // Copies of constructors without arguments don't allocate.
+        assert_eq!(
+            messages("True = 0 1 main = 0 { load_global True x = copy True return_symbol x }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages(
+                "True = 0 1 main = 0 { load_global True x = copy True free_term x return_symbol x }"
+            ),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -1,11 +1,67 @@
 use super::*;
+use crate::{
+    lex::{Comment, LexError},
+    report::{Diagnostic, Level},
+};
 use grammar::*;
 use lalrpop_util::{lalrpop_mod, ParseError};
+use lex::{Lexer, Tok};
+use std::collections::BTreeMap;
 
 lalrpop_mod!(grammar, "/lir/grammar.rs");
 
-pub fn parse(input: &str) -> Result<Program, ParseError<usize, Token, &str>> {
-    ProgramParser::new().parse(input)
+pub type Error<'input> = ParseError<usize, Tok<'input>, LexError>;
+
+/// What the formatter needs to know about the source of a program besides
+/// the program itself.
+#[derive(Debug, Default)]
+pub struct Trivia {
+    pub comments: Vec<Comment>,
+    /// Where each block opens and closes.
+    pub blocks: BTreeMap<usize, usize>,
+}
+
+/// Parses a program. A global that is defined more than once keeps its last
+/// definition, which `parse_globals` and `collect` can report.
+pub fn parse(input: &str) -> Result<Program, Error<'_>> {
+    let (program, _) = collect(parse_globals(input)?, Level::Warning);
+    Ok(program)
+}
+
+/// Parses the globals of a program in the order they are defined, including
+/// any that are defined more than once.
+pub fn parse_globals(input: &str) -> Result<Vec<(Name, Global)>, Error<'_>> {
+    parse_with_trivia(input).map(|(globals, _)| globals)
+}
+
+pub fn parse_with_trivia(input: &str) -> Result<(Vec<(Name, Global)>, Trivia), Error<'_>> {
+    let mut lexer = Lexer::new(input);
+    let globals = GlobalsParser::new().parse(&mut lexer)?;
+    let blocks = std::mem::take(&mut lexer.blocks);
+    let trivia = Trivia {
+        comments: lexer.comments(),
+        blocks,
+    };
+    Ok((globals, trivia))
+}
+
+/// Collects globals into a program in the order they are first defined. A
+/// global that is defined more than once keeps its last definition, and every
+/// definition that replaces another is reported at `level`.
+pub fn collect(globals: Vec<(Name, Global)>, level: Level) -> (Program, Vec<Diagnostic>) {
+    let mut program = Program::new();
+    let mut diagnostics = vec![];
+    for (name, global) in globals {
+        if let Some(previous) = program.get(&name) {
+            diagnostics.push(
+                Diagnostic::new(level, format!("global {} is defined more than once", name))
+                    .with_label(global.span().clone(), "redefined here")
+                    .with_label(previous.span().clone(), "previously defined here"),
+            );
+        }
+        program.insert(name, global);
+    }
+    (program, diagnostics)
 }
 
 #[cfg(test)]
@@ -16,37 +72,129 @@ mod test {
     fn test_program() {
         assert_eq!(
             parse("main = 0 {}").unwrap(),
-            HashMap::from([(
+            Program::from([(
                 "main".to_string(),
                 Global::Fun {
                     arity: 0,
                     block: vec![],
+                    span: 0..4,
                 }
             )])
         );
 
         assert_eq!(
             parse("True = 0 1").unwrap(),
-            HashMap::from([((
+            Program::from([(
                 "True".to_string(),
                 Global::Ctor {
                     arity: 0,
-                    symbol: 1
+                    symbol: Some(1),
+                    span: 0..4,
                 }
-            ))])
+            )])
+        );
+
+        assert_eq!(
+            parse("True = 0").unwrap(),
+            Program::from([(
+                "True".to_string(),
+                Global::Ctor {
+                    arity: 0,
+                    symbol: None,
+                    span: 0..4,
+                }
+            )])
         );
 
         assert_eq!(
             parse("f = 1 { return x }").unwrap(),
-            HashMap::from([((
+            Program::from([(
                 "f".to_string(),
                 Global::Fun {
                     arity: 1,
                     block: vec![Op::Return {
-                        var: "x".to_string()
+                        var: "x".to_string(),
+                        span: 8..16,
                     }],
+                    span: 0..1,
                 }
-            ))])
+            )])
+        );
+
+        assert_eq!(
+            parse("main = 0 { switch x { True { todo } } }").unwrap(),
+            Program::from([(
+                "main".to_string(),
+                Global::Fun {
+                    arity: 0,
+                    block: vec![Op::Switch {
+                        var: "x".to_string(),
+                        cases: vec![Case {
+                            global: "True".to_string(),
+                            block: vec![Op::Todo { span: 29..33 }],
+                            span: 22..26,
+                        }],
+                        span: 11..19,
+                    }],
+                    span: 0..4,
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            parse("-- main\nmain {- = 1 -} = 0 { # {\n}").unwrap(),
+            Program::from([(
+                "main".to_string(),
+                Global::Fun {
+                    arity: 0,
+                    block: vec![],
+                    span: 8..12,
+                }
+            )])
+        );
+        assert_eq!(
+            parse("main = 0 {} {- {- -}").unwrap_err(),
+            ParseError::User {
+                error: LexError::UnterminatedComment { span: 12..20 }
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let (program, diagnostics) =
+            collect(parse_globals("f = 0 g = 1 f = 2").unwrap(), Level::Error);
+        assert_eq!(
+            program.iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    &"f".to_string(),
+                    &Global::Ctor {
+                        arity: 2,
+                        symbol: None,
+                        span: 12..13,
+                    }
+                ),
+                (
+                    &"g".to_string(),
+                    &Global::Ctor {
+                        arity: 1,
+                        symbol: None,
+                        span: 6..7,
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(Level::Error, "global f is defined more than once")
+                    .with_label(12..13, "redefined here")
+                    .with_label(0..1, "previously defined here")
+            ]
         );
     }
 }
@@ -0,0 +1,457 @@
+use super::*;
+use parse::Trivia;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Prints a program as LIR source. Constructors come first, then functions,
+/// each sorted by name, so the output doesn't depend on the order they were
+/// defined in.
+///
+/// This is the canonical format that `yoke fmt` rewrites files in. Parsing the
+/// output gives back the same program, apart from spans, as long as the names
+/// in it are valid LIR names, which they always are in a parsed program.
+pub fn print(program: &Program) -> String {
+    Printer::new(program, "", &Trivia::default()).program(program)
+}
+
+/// Prints a program like `print`, keeping the comments from the source it was
+/// parsed from.
+///
+/// A comment on its own line stays before the global, instruction, case or
+/// closing brace after it, and a comment after code stays at the end of the
+/// line. The comments at the start of the file that end in a blank line stay
+/// at the start, even if the globals are reordered.
+pub fn format(program: &Program, input: &str, trivia: &Trivia) -> String {
+    Printer::new(program, input, trivia).program(program)
+}
+
+struct Printer<'a> {
+    output: String,
+    blocks: &'a BTreeMap<usize, usize>,
+    /// Where the source ends, which comments at the end of it come before.
+    end: usize,
+    header: Vec<&'a str>,
+    /// Comments on their own lines, by where what they come before starts.
+    leading: HashMap<usize, Vec<&'a str>>,
+    /// Comments after code, by where the line it is on starts.
+    trailing: HashMap<usize, Vec<&'a str>>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(program: &Program, input: &'a str, trivia: &'a Trivia) -> Self {
+        let mut printer = Printer {
+            output: String::new(),
+            blocks: &trivia.blocks,
+            end: input.len(),
+            header: vec![],
+            leading: HashMap::new(),
+            trailing: HashMap::new(),
+        };
+
+        // Everything that is printed at the start of a line, which is where
+        // comments can be put.
+        let mut anchors = vec![printer.end];
+        for global in program.values() {
+            match global {
+                Global::Ctor { span, .. } => anchors.push(span.start),
+                Global::Fun { block, span, .. } => {
+                    anchors.push(span.start);
+                    printer.block_anchors(block, span.end, &mut anchors);
+                }
+            }
+        }
+        anchors.sort();
+
+        let first = anchors[0];
+        let mut header = 0;
+        for (i, comment) in trivia.comments.iter().enumerate() {
+            if comment.span.end > first {
+                break;
+            }
+            let next = trivia
+                .comments
+                .get(i + 1)
+                .map_or(first, |next| next.span.start.min(first));
+            if input[comment.span.end..next].matches('\n').count() > 1 {
+                header = i + 1;
+            }
+        }
+
+        for (i, comment) in trivia.comments.iter().enumerate() {
+            let text = &input[comment.span.clone()];
+            if i < header {
+                printer.header.push(text);
+            } else if comment.trailing {
+                let line = anchors.partition_point(|anchor| *anchor <= comment.span.start);
+                let anchor = anchors[line - 1];
+                printer.trailing.entry(anchor).or_default().push(text);
+            } else {
+                let next = anchors.partition_point(|anchor| *anchor < comment.span.end);
+                let anchor = anchors[next];
+                printer.leading.entry(anchor).or_default().push(text);
+            }
+        }
+        printer
+    }
+
+    fn block_anchors(&self, block: &Block, start: usize, anchors: &mut Vec<usize>) {
+        for op in block {
+            anchors.push(op.span().start);
+            if let Op::Switch { cases, span, .. } = op {
+                for case in cases {
+                    anchors.push(case.span.start);
+                    self.block_anchors(&case.block, case.span.end, anchors);
+                }
+                anchors.extend(self.close(span.end));
+            }
+        }
+        anchors.extend(self.close(start));
+    }
+
+    /// Where the first block after `start` closes, if it is known.
+    fn close(&self, start: usize) -> Option<usize> {
+        self.blocks.range(start..).next().map(|(_, close)| *close)
+    }
+
+    fn program(mut self, program: &Program) -> String {
+        let mut names = program.keys().collect::<Vec<_>>();
+        names.sort_by_key(|name| (matches!(program[*name], Global::Fun { .. }), *name));
+
+        for text in std::mem::take(&mut self.header) {
+            self.output.push_str(text);
+            self.output.push('\n');
+        }
+        for name in names {
+            if !self.output.is_empty() {
+                self.output.push('\n');
+            }
+            match &program[name] {
+                Global::Ctor {
+                    arity,
+                    symbol,
+                    span,
+                } => {
+                    self.leading(span.start, 0);
+                    match symbol {
+                        Some(symbol) => write!(self.output, "{} = {} {}", name, arity, symbol),
+                        None => write!(self.output, "{} = {}", name, arity),
+                    }
+                    .unwrap();
+                    self.trailing(span.start);
+                }
+                Global::Fun { arity, block, span } => {
+                    self.leading(span.start, 0);
+                    write!(self.output, "{} = {} ", name, arity).unwrap();
+                    self.block(block, 0, span.start, self.close(span.end));
+                }
+            }
+            self.output.push('\n');
+        }
+
+        if self.leading.contains_key(&self.end) {
+            if !self.output.is_empty() {
+                self.output.push('\n');
+            }
+            self.leading(self.end, 0);
+        }
+        self.output
+    }
+
+    /// Prints a block, which opens at the end of the line starting at `line`.
+    fn block(&mut self, block: &Block, depth: usize, line: usize, close: Option<usize>) {
+        self.output.push('{');
+        self.trailing(line);
+        self.output.push('\n');
+        for op in block {
+            self.op(op, depth + 1);
+        }
+        self.close_brace(depth, close);
+    }
+
+    fn close_brace(&mut self, depth: usize, close: Option<usize>) {
+        if let Some(close) = close {
+            self.leading(close, depth + 1);
+        }
+        indent(depth, &mut self.output);
+        self.output.push('}');
+        if let Some(close) = close {
+            self.trailing(close);
+        }
+    }
+
+    fn op(&mut self, op: &Op, depth: usize) {
+        let start = op.span().start;
+        self.leading(start, depth);
+        indent(depth, &mut self.output);
+        let output = &mut self.output;
+        match op {
+            Op::LoadGlobal { global, .. } => write!(output, "load_global {}", global),
+            Op::LoadArg {
+                name, var, index, ..
+            } => write!(output, "{} = load_arg {} {}", name, var, index),
+            Op::NewApp {
+                name, var, args, ..
+            } => write!(output, "{} = new_app {} {}", name, var, print_args(args)),
+            Op::NewPartial {
+                name, var, args, ..
+            } => write!(
+                output,
+                "{} = new_partial {} {}",
+                name,
+                var,
+                print_args(args)
+            ),
+            Op::ApplyPartial {
+                name, var, args, ..
+            } => write!(
+                output,
+                "{} = apply_partial {} {}",
+                name,
+                var,
+                print_args(args)
+            ),
+            Op::Copy { name, var, .. } => write!(output, "{} = copy {}", name, var),
+            Op::Eval { var, .. } => write!(output, "eval {}", var),
+            Op::FreeArgs { var, .. } => write!(output, "free_args {}", var),
+            Op::FreeTerm { var, .. } => write!(output, "free_term {}", var),
+            Op::ReturnSymbol { var, .. } => write!(output, "return_symbol {}", var),
+            Op::Return { var, .. } => write!(output, "return {}", var),
+            Op::Switch { var, cases, span } => {
+                write!(output, "switch {} {{", var).unwrap();
+                self.trailing(start);
+                self.output.push('\n');
+                for case in cases {
+                    self.leading(case.span.start, depth + 1);
+                    indent(depth + 1, &mut self.output);
+                    write!(self.output, "{} ", case.global).unwrap();
+                    let close = self.close(case.span.end);
+                    self.block(&case.block, depth + 1, case.span.start, close);
+                    self.output.push('\n');
+                }
+                self.close_brace(depth, self.close(span.end));
+                self.output.push('\n');
+                return;
+            }
+            Op::Todo { .. } => write!(output, "todo"),
+        }
+        .unwrap();
+        self.trailing(start);
+        self.output.push('\n');
+    }
+
+    /// Prints the comments on their own lines before `anchor`.
+    fn leading(&mut self, anchor: usize, depth: usize) {
+        for text in self.leading.remove(&anchor).unwrap_or_default() {
+            indent(depth, &mut self.output);
+            self.output.push_str(text);
+            self.output.push('\n');
+        }
+    }
+
+    /// Prints the comments after the code on the line starting at `anchor`.
+    fn trailing(&mut self, anchor: usize) {
+        for text in self.trailing.remove(&anchor).unwrap_or_default() {
+            self.output.push(' ');
+            self.output.push_str(text);
+        }
+    }
+}
+
+fn print_args(args: &[Name]) -> String {
+    match args {
+        [] => "{}".to_string(),
+        args => format!("{{ {} }}", args.join(" ")),
+    }
+}
+
+fn indent(depth: usize, output: &mut String) {
+    (0..depth).for_each(|_| output.push_str("  "));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_print() {
+        let input = "True = 0 1 False = 0
+                     main = 0 {
+                       load_global not load_global True
+                       x = new_app not { True } y = new_partial not { }
+                       eval x
+                       switch x { True { return_symbol x } False { todo } }
+                     }
+                     not = 1 {
+                       x = load_arg self 0 free_args self eval x
+                       switch x { True { load_global False return False } }
+                     }";
+        let output = print(&parse::parse(input).unwrap());
+        assert_eq!(
+            output,
+            "False = 0
+
+True = 0 1
+
+main = 0 {
+  load_global not
+  load_global True
+  x = new_app not { True }
+  y = new_partial not {}
+  eval x
+  switch x {
+    True {
+      return_symbol x
+    }
+    False {
+      todo
+    }
+  }
+}
+
+not = 1 {
+  x = load_arg self 0
+  free_args self
+  eval x
+  switch x {
+    True {
+      load_global False
+      return False
+    }
+  }
+}
+"
+        );
+        assert_eq!(print(&parse::parse(&output).unwrap()), output);
+    }
+
+    #[test]
+    fn test_format() {
+        let input = "-- exit: 1
+-- A header.
+
+main = 0 { -- Starts here.
+  -- Not.
+  load_global not
+  load_global True {- Or False. -}
+  x = new_app not { True }
+  eval x
+  switch x {
+    -- Only True.
+    True { return_symbol x
+      # Unreachable.
+    } -- True.
+    {- False {
+      todo
+    } -}
+  }
+}
+
+-- Before True.
+True = 0 1
+not = 1 { x = load_arg self 0 free_args self eval x
+  switch x { True { load_global False return False } }
+}
+False = 0
+-- The end.
+";
+        let (globals, trivia) = parse::parse_with_trivia(input).unwrap();
+        let program = Program::from_iter(globals);
+        let output = format(&program, input, &trivia);
+        assert_eq!(
+            output,
+            "-- exit: 1
+-- A header.
+
+False = 0
+
+-- Before True.
+True = 0 1
+
+main = 0 { -- Starts here.
+  -- Not.
+  load_global not
+  load_global True {- Or False. -}
+  x = new_app not { True }
+  eval x
+  switch x {
+    -- Only True.
+    True {
+      return_symbol x
+      # Unreachable.
+    } -- True.
+    {- False {
+      todo
+    } -}
+  }
+}
+
+not = 1 {
+  x = load_arg self 0
+  free_args self
+  eval x
+  switch x {
+    True {
+      load_global False
+      return False
+    }
+  }
+}
+
+-- The end.
+"
+        );
+        let (globals, trivia) = parse::parse_with_trivia(&output).unwrap();
+        let program = Program::from_iter(globals);
+        assert_eq!(format(&program, &output, &trivia), output);
+    }
+
+    proptest! {
+        /// Checks that printing and parsing a program gives it back. Printing
+        /// ignores spans, so printing it again gives the same text if it is
+        /// the same program.
+        #[test]
+        fn test_round_trip(choices in proptest::collection::vec(any::<u32>(), 0..256)) {
+            let output = print(&generate::generate(&choices));
+            let program = parse::parse(&output);
+            prop_assert!(program.is_ok(), "{}", output);
+            prop_assert_eq!(print(&program.unwrap()), output);
+        }
+
+        /// Checks that formatting a program with comments added between its
+        /// lines and at their ends keeps them and doesn't change the program,
+        /// and that formatting it again changes nothing.
+        #[test]
+        fn test_format_round_trip(
+            choices in proptest::collection::vec(any::<u32>(), 0..256),
+            comments in proptest::collection::vec(0..4u8, 0..64),
+        ) {
+            let expected = print(&generate::generate(&choices));
+            let mut input = String::new();
+            let mut texts = vec![];
+            for (i, line) in expected.lines().enumerate() {
+                let text = match comments.get(i) {
+                    Some(1) => format!("-- {}", i),
+                    Some(2) => format!("# {}", i),
+                    Some(3) => format!("{{- {} {{- -}} -}}", i),
+                    _ => String::new(),
+                };
+                if i % 2 == 0 {
+                    input.push_str(&format!("{}\n{}\n", text, line));
+                } else {
+                    input.push_str(&format!("{} {}\n", line, text));
+                }
+                texts.extend((!text.is_empty()).then_some(text));
+            }
+
+            let (globals, trivia) = parse::parse_with_trivia(&input).unwrap();
+
+            let program = Program::from_iter(globals);
+            let output = format(&program, &input, &trivia);
+            prop_assert!(texts.iter().all(|text| output.contains(text)), "{}", output);
+            let (globals, trivia) = parse::parse_with_trivia(&output).unwrap();
+            let program = Program::from_iter(globals);
+            prop_assert_eq!(print(&program), expected);
+            prop_assert_eq!(format(&program, &output, &trivia), output);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::*;
+use crate::report::{Diagnostic, Level};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: HashMap<Name, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Every constructor and its symbol, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), *symbol))
+    }
+
+    /// The constructor with a symbol, if there is one.
+    pub fn name(&self, symbol: Symbol) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(_, s)| **s == symbol)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Assigns a symbol to every constructor in the program.
+///
+/// Constructors with a symbol written by hand keep it. The rest are given the
+/// smallest unused symbols in name order, starting from `1` since `0` is the
+/// symbol of every function. Hand-written symbols that are `0` or that are
+/// shared by several constructors are reported as errors.
+pub fn intern(program: &Program) -> (SymbolTable, Vec<Diagnostic>) {
+    let mut ctors = program
+        .iter()
+        .filter_map(|(name, global)| match global {
+            Global::Ctor { symbol, span, .. } => Some((name, *symbol, span)),
+            Global::Fun { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    ctors.sort_by_key(|(name, ..)| *name);
+
+    let mut used = BTreeMap::<Symbol, Vec<(&Name, &Span)>>::new();
+    for (name, symbol, span) in &ctors {
+        if let Some(symbol) = symbol {
+            used.entry(*symbol).or_default().push((name, span));
+        }
+    }
+
+    let mut diagnostics = vec![];
+    if let Some(ctors) = used.get(&0) {
+        for (name, span) in ctors {
+            diagnostics.push(
+                Diagnostic::new(Level::Error, format!("{} can't have the symbol 0", name))
+                    .with_label((*span).clone(), "0 is the symbol of every function"),
+            );
+        }
+    }
+    for (symbol, ctors) in used.iter().filter(|(_, ctors)| ctors.len() > 1) {
+        let names = ctors
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let diagnostic = Diagnostic::new(
+            Level::Error,
+            format!("constructors {} have the same symbol {}", names, symbol),
+        );
+        diagnostics.push(ctors.iter().fold(diagnostic, |diagnostic, (name, span)| {
+            diagnostic.with_label((*span).clone(), format!("{} has symbol {}", name, symbol))
+        }));
+    }
+
+    let mut table = SymbolTable::default();
+    let mut next = 1..;
+    for (name, symbol, _) in ctors {
+        let symbol = symbol.unwrap_or_else(|| {
+            next.by_ref()
+                .find(|symbol| !used.contains_key(symbol))
+                .unwrap()
+        });
+        table.symbols.insert(name.clone(), symbol);
+    }
+
+    (table, diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let (table, diagnostics) = intern(&parse::parse("A = 0 B = 0 2 C = 0 D = 1 1").unwrap());
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(table.symbol("A"), Some(3));
+        assert_eq!(table.symbol("B"), Some(2));
+        assert_eq!(table.symbol("C"), Some(4));
+        assert_eq!(table.symbol("D"), Some(1));
+        assert_eq!(table.name(2), Some("B"));
+        assert_eq!(table.name(5), None);
+    }
+
+    #[test]
+    fn test_collision() {
+        let (_, diagnostics) = intern(&parse::parse("A = 0 1 B = 0 1 C = 0 2").unwrap());
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(Level::Error, "constructors A, B have the same symbol 1")
+                    .with_label(0..1, "A has symbol 1")
+                    .with_label(8..9, "B has symbol 1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero() {
+        let (_, diagnostics) =
+            intern(&parse::parse("A = 0 0 main = 0 { load_global A return_symbol A }").unwrap());
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(Level::Error, "A can't have the symbol 0")
+                .with_label(0..1, "0 is the symbol of every function")]
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use super::*;
+use crate::report::{arguments, Diagnostic, Level};
+
+/// Checks the rules of the LIR that code generation relies on but doesn't
+/// check itself, so that breaking them is reported as an error rather than
+/// producing invalid LLVM IR or a program that misbehaves at runtime.
+///
+/// Locals are tracked with the arity of the global they were loaded from,
+/// where it is known, so that `load_arg` and `new_app` can be checked.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    match program.get("main") {
+        None => diagnostics.push(Diagnostic::new(Level::Error, "no main function")),
+        Some(Global::Ctor { span, .. }) => diagnostics.push(
+            Diagnostic::new(Level::Error, "main must be a function")
+                .with_label(span.clone(), "main is a constructor"),
+        ),
+        Some(Global::Fun { arity, span, .. }) if *arity != 0 => diagnostics.push(
+            Diagnostic::new(Level::Error, "main must have an arity of 0")
+                .with_label(span.clone(), format!("main has an arity of {}", arity)),
+        ),
+        Some(Global::Fun { .. }) => {}
+    }
+
+    if let Some(global) = program.get("_") {
+        diagnostics.push(
+            Diagnostic::new(Level::Error, "_ can't be the name of a global")
+                .with_label(global.span().clone(), "_ is used for default cases"),
+        );
+    }
+
+    let mut names = program.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let Global::Fun { arity, block, span } = &program[name] else {
+            continue;
+        };
+        let main = name == "main";
+        let mut scope = HashMap::new();
+        if !main {
+            scope.insert("self".to_string(), Some(*arity));
+        }
+        let mut validator = Validator {
+            program,
+            main,
+            locals: vec![scope],
+            diagnostics: &mut diagnostics,
+        };
+        validator.block(block, span);
+    }
+
+    diagnostics
+}
+
+struct Validator<'a> {
+    program: &'a Program,
+    main: bool,
+    /// The arity of every local in scope, if it is known.
+    locals: Vec<HashMap<Name, Option<Arity>>>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    /// Checks that a block ends with exactly one terminator. `span` is used
+    /// when the block is empty.
+    fn block(&mut self, block: &Block, span: &Span) {
+        for (i, op) in block.iter().enumerate() {
+            self.op(op);
+            if is_terminator(op) {
+                if let Some(next) = block.get(i + 1) {
+                    self.error(
+                        "unreachable instruction",
+                        next.span(),
+                        "this comes after the end of the block",
+                    );
+                }
+                return;
+            }
+        }
+
+        let span = block.last().map_or(span, Op::span);
+        self.error(
+            "block doesn't end with return, return_symbol, switch or todo",
+            span,
+            "the block ends here",
+        );
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::LoadGlobal { global, .. } => {
+                let arity = self.program.get(global).map(|global| match global {
+                    Global::Ctor { arity, .. } | Global::Fun { arity, .. } => *arity,
+                });
+                self.define(global, arity);
+            }
+            Op::LoadArg {
+                name,
+                var,
+                index,
+                span,
+            } => {
+                self.use_local(var, span);
+                let arity = self.arity(var);
+                if let Some(arity) = arity.filter(|arity| *index >= *arity as Index) {
+                    self.error(
+                        format!("argument index {} is out of bounds", index),
+                        span,
+                        format!("{} has {}", var, arguments(arity as usize)),
+                    );
+                }
+                self.define(name, None);
+            }
+            Op::NewApp {
+                name,
+                var,
+                args,
+                span,
+            } => {
+                self.use_locals(var, args, span);
+                let arity = self.arity(var);
+                if let Some(arity) = arity.filter(|arity| args.len() != *arity as usize) {
+                    self.error(
+                        format!("new_app of {} needs {}", var, arguments(arity as usize)),
+                        span,
+                        format!("{} given here", arguments(args.len())),
+                    );
+                }
+                self.define(name, arity);
+            }
+            Op::NewPartial {
+                name,
+                var,
+                args,
+                span,
+            }
+            | Op::ApplyPartial {
+                name,
+                var,
+                args,
+                span,
+            } => {
+                self.use_locals(var, args, span);
+                self.define(name, None);
+            }
+            Op::Copy { name, var, span } => {
+                self.use_local(var, span);
+                let arity = self.arity(var);
+                self.define(name, arity);
+            }
+            Op::Eval { var, span } => {
+                // The term is replaced by its result, which can be any
+                // constructor.
+                self.use_local(var, span);
+                if self.is_defined(var) {
+                    self.define(var, None);
+                }
+            }
+            Op::FreeArgs { var, span } | Op::FreeTerm { var, span } => self.use_local(var, span),
+            Op::ReturnSymbol { var, span } => {
+                self.use_local(var, span);
+                if !self.main {
+                    self.error(
+                        "return_symbol is only valid in main",
+                        span,
+                        "use return to return a term",
+                    );
+                }
+            }
+            Op::Return { var, span } => {
+                self.use_local(var, span);
+                if self.main {
+                    self.error(
+                        "return is not valid in main",
+                        span,
+                        "use return_symbol to return a symbol",
+                    );
+                }
+            }
+            Op::Switch { var, cases, span } => {
+                self.use_local(var, span);
+                for (i, case) in cases.iter().enumerate() {
+                    match self.program.get(&case.global) {
+                        _ if case.is_default() => {
+                            if i + 1 != cases.len() {
+                                self.error(
+                                    "the _ case must be the last case",
+                                    &case.span,
+                                    "the cases after this are never taken",
+                                );
+                            }
+                        }
+                        Some(Global::Ctor { .. }) => {}
+                        Some(Global::Fun { .. }) => self.error(
+                            format!("{} is not a constructor", case.global),
+                            &case.span,
+                            "switch cases must be constructors",
+                        ),
+                        None => self.error(
+                            format!("no constructor with name {}", case.global),
+                            &case.span,
+                            format!("{} is not defined", case.global),
+                        ),
+                    }
+                    // Inside a case, the term is known to be its constructor.
+                    let arity = match self.program.get(&case.global) {
+                        Some(Global::Ctor { arity, .. }) if !case.is_default() => Some(*arity),
+                        _ => None,
+                    };
+                    self.locals.push(HashMap::new());
+                    if self.is_defined(var) {
+                        self.define(var, arity);
+                    }
+                    self.block(&case.block, &case.span);
+                    self.locals.pop();
+                }
+            }
+            Op::Todo { .. } => {}
+        }
+    }
+
+    fn use_locals(&mut self, var: &str, args: &[Name], span: &Span) {
+        self.use_local(var, span);
+        args.iter().for_each(|arg| self.use_local(arg, span));
+    }
+
+    /// Unknown locals are reported by code generation, but `self` in `main`
+    /// gets its own error since it is an easy mistake to make.
+    fn use_local(&mut self, var: &str, span: &Span) {
+        if self.main && var == "self" && !self.is_defined(var) {
+            self.error(
+                "self is not available in main",
+                span,
+                "main has no arguments",
+            );
+        }
+    }
+
+    fn define(&mut self, name: &str, arity: Option<Arity>) {
+        self.locals
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), arity);
+    }
+
+    fn is_defined(&self, var: &str) -> bool {
+        self.locals.iter().any(|scope| scope.contains_key(var))
+    }
+
+    fn arity(&self, var: &str) -> Option<Arity> {
+        self.locals
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(var))
+            .copied()
+            .flatten()
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: &Span, label: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::new(Level::Error, message).with_label(span.clone(), label));
+    }
+}
+
+fn is_terminator(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::Return { .. } | Op::ReturnSymbol { .. } | Op::Switch { .. } | Op::Todo { .. }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(input: &str) -> Vec<String> {
+        check(&parse::parse(input).unwrap())
+            .into_iter()
+            .flat_map(|diagnostic| {
+                let labels = diagnostic.labels.into_iter().map(|(_, label)| label);
+                std::iter::once(diagnostic.message).chain(labels)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            messages(
+                "True = 0 1
+                 False = 0 2
+                 main = 0 {
+                   load_global const
+                   load_global True
+                   load_global False
+                   x = new_app const { True False }
+                   eval x
+                   switch x {
+                     True { return_symbol x }
+                     False { todo }
+                   }
+                 }
+                 const = 2 {
+                   x = load_arg self 1
+                   return x
+                 }"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_main() {
+        assert_eq!(messages("f = 0 { todo }"), vec!["no main function"]);
+        assert_eq!(
+            messages("main = 1 { todo }"),
+            vec!["main must have an arity of 0", "main has an arity of 1"]
+        );
+        assert_eq!(
+            check(&parse::parse("main = 0 { x = load_arg self 0 todo }").unwrap()),
+            vec![
+                Diagnostic::new(Level::Error, "self is not available in main")
+                    .with_label(11..30, "main has no arguments")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_returns() {
+        assert_eq!(
+            messages("main = 0 { return self } f = 0 { return_symbol self }"),
+            vec![
+                "return_symbol is only valid in main",
+                "use return to return a term",
+                "self is not available in main",
+                "main has no arguments",
+                "return is not valid in main",
+                "use return_symbol to return a symbol",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_terminators() {
+        assert_eq!(
+            messages("True = 0 main = 0 { load_global True switch True { True { } } }"),
+            vec![
+                "block doesn't end with return, return_symbol, switch or todo",
+                "the block ends here",
+            ]
+        );
+        assert_eq!(
+            messages("main = 0 { todo load_global main }"),
+            vec![
+                "unreachable instruction",
+                "this comes after the end of the block",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arities() {
+        assert_eq!(
+            messages(
+                "True = 0 1
+                 main = 0 { load_global f load_global True x = new_app f { True } todo }
+                 f = 2 { x = load_arg self 2 return x }"
+            ),
+            vec![
+                "argument index 2 is out of bounds",
+                "self has 2 arguments",
+                "new_app of f needs 2 arguments",
+                "1 argument given here",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(
+            messages(
+                "Nothing = 0 Just = 1
+                 main = 0 { load_global c eval c switch c { Just { x = load_arg c 0 return_symbol x } Nothing { return_symbol c } } }
+                 c = 0 { load_global Just load_global Nothing j = new_app Just { Nothing } return j }"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages(
+                "Nothing = 0 Just = 1
+                 main = 0 { load_global Nothing switch Nothing { Nothing { x = load_arg Nothing 0 todo } _ { todo } } }"
+            ),
+            vec!["argument index 0 is out of bounds", "Nothing has 0 arguments"]
+        );
+    }
+
+    #[test]
+    fn test_cases() {
+        assert_eq!(
+            messages("main = 0 { load_global main switch main { main { todo } Nil { todo } } }"),
+            vec![
+                "main is not a constructor",
+                "switch cases must be constructors",
+                "no constructor with name Nil",
+                "Nil is not defined",
+            ]
+        );
+        assert_eq!(
+            messages(
+                "True = 0 False = 0
+                 main = 0 { load_global True switch True { _ { todo } True { todo } } }"
+            ),
+            vec![
+                "the _ case must be the last case",
+                "the cases after this are never taken",
+            ]
+        );
+        assert_eq!(
+            messages("_ = 0 main = 0 { todo }"),
+            vec![
+                "_ can't be the name of a global",
+                "_ is used for default cases",
+            ]
+        );
+    }
+}
@@ -1,45 +1,161 @@
+mod fmt;
+mod lex;
 mod lir;
 mod mir;
+mod repl;
 mod report;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// The Yoke compiler
 #[derive(Parser, Debug)]
-#[command(name = "yoke", version, about, long_about = None)]
+#[command(
+    name = "yoke",
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// File to compile
-    input: String,
+    #[arg(required = true)]
+    input: Option<String>,
 
     /// Interpret input as code instead of a filename
     #[arg(short, long)]
     code: bool,
 
+    /// Interpret input as MIR instead of LIR (implied by a .mir extension)
+    #[arg(short, long)]
+    mir: bool,
+
+    /// Treat non-exhaustive patterns in MIR as errors
+    #[arg(long)]
+    deny_non_exhaustive: bool,
+
+    /// Treat globals that are defined more than once as errors
+    #[arg(long)]
+    deny_duplicates: bool,
+
+    /// Warn about use-after-free, double frees and leaks in LIR
+    #[arg(long)]
+    check_ownership: bool,
+
     /// Evaluate instead of compile
     #[arg(short, long)]
     eval: bool,
 
-    /// Optimization level
+    /// Evaluate and print the result as a term, forcing it to a depth (unlimited by default)
+    #[arg(short, long, value_name = "DEPTH", num_args = 0..=1, require_equals = true)]
+    print: Option<Option<usize>>,
+
+    /// Evaluate with the reference interpreter instead of LLVM
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "output", "emit", "emit_unoptimized", "target", "cpu", "features", "passes", "debug",
+        ],
+    )]
+    interp: bool,
+
+    /// Where to write the executable (defaults to the input file without its extension)
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+
+    /// Artifacts to write next to the output (defaults to exe unless evaluating)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "KIND")]
+    emit: Vec<Emit>,
+
+    /// Write the LLVM artifacts before optimizing instead of after
+    #[arg(long, requires = "emit")]
+    emit_unoptimized: bool,
+
+    /// Target triple to compile for (defaults to the host)
+    #[arg(long, value_name = "TRIPLE")]
+    target: Option<String>,
+
+    /// Target CPU to generate code for
+    #[arg(long)]
+    cpu: Option<String>,
+
+    /// Target features to enable or disable, like +avx2,-sse4.1
+    #[arg(long)]
+    features: Option<String>,
+
+    /// Optimization level (s and z optimize for size)
     #[arg(
         short = 'O',
         long,
         value_name = "LEVEL",
-        value_parser = clap::value_parser!(u8).range(0..=3),
-        default_value_t = 0,
+        value_parser = ["0", "1", "2", "3", "s", "z"],
+        default_value = "0",
     )]
-    optimize: u8,
+    optimize: String,
+
+    /// LLVM pass pipeline to run instead of the one chosen by -O
+    #[arg(long, value_name = "PIPELINE")]
+    passes: Option<String>,
+
+    /// Generate DWARF debug info mapping the program back to its source
+    #[arg(short = 'g', long)]
+    debug: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Emit {
+    Lir,
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Evaluate definitions and expressions interactively
+    Repl {
+        /// File of definitions to start with
+        file: Option<String>,
+
+        /// Interpret input as MIR instead of LIR (implied by a .mir extension)
+        #[arg(short, long)]
+        mir: bool,
+    },
+
+    /// Rewrite LIR files in the canonical format
+    Fmt {
+        /// Files to format
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// List the files that aren't formatted instead of rewriting them
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 fn main() {
-    std::process::exit(compile(Args::parse()));
+    let args = Args::parse();
+    let code = match args.command {
+        Some(Command::Repl { file, mir }) => repl::repl(file, mir),
+        Some(Command::Fmt { files, check }) => fmt::fmt(files, check),
+        None => compile(args),
+    };
+    std::process::exit(code);
 }
 
 fn compile(args: Args) -> i32 {
+    let path = args.input.unwrap_or_default();
     let (file, input) = if args.code {
-        ("<cli>", args.input)
+        ("<cli>", path)
     } else {
-        match std::fs::read_to_string(&args.input) {
-            Ok(input) => (args.input.as_str(), input),
+        match std::fs::read_to_string(&path) {
+            Ok(input) => (path.as_str(), input),
             Err(e) => {
                 eprintln!("Failed to read file: {}", e);
                 return 1;
@@ -47,50 +163,363 @@ fn compile(args: Args) -> i32 {
         }
     };
 
-    let program = match lir::parse::parse(&input) {
+    let mir = args.mir || file.ends_with(".mir");
+    let duplicates = Some(if args.deny_duplicates {
+        report::Level::Error
+    } else {
+        report::Level::Warning
+    });
+    let program = match lower(file, &input, mir, args.deny_non_exhaustive, duplicates) {
         Ok(program) => program,
-        Err(errors) => {
-            report::print_parse_errors(file, &input, errors);
-            return 2;
+        Err(code) => return code,
+    };
+    if let Err(code) = check(file, &input, &program, args.check_ownership) {
+        return code;
+    }
+
+    if args.interp {
+        return interp(file, &input, &program, args.print);
+    }
+
+    let output = match args.output {
+        Some(output) => output.into(),
+        None => match std::path::Path::new(file).with_extension("") {
+            output if args.code || output.as_os_str() == file => "a.out".into(),
+            output => output,
+        },
+    };
+
+    if args.emit.contains(&Emit::Lir) {
+        let path = output.with_extension("lir");
+        if path.as_os_str() == file {
+            eprintln!(
+                "Refusing to overwrite {}, pick another output with -o",
+                file
+            );
+            return 6;
         }
+        if let Err(e) = std::fs::write(&path, lir::print::print(&program)) {
+            eprintln!("Failed to write file: {}", e);
+            return 6;
+        }
+    }
+
+    let emit = if args.emit.is_empty() && !args.eval && args.print.is_none() {
+        vec![lir::compile::Emit::Exe]
+    } else {
+        args.emit
+            .iter()
+            .filter_map(|emit| match emit {
+                Emit::Lir => None,
+                Emit::LlvmIr => Some(lir::compile::Emit::LlvmIr),
+                Emit::LlvmBc => Some(lir::compile::Emit::LlvmBc),
+                Emit::Asm => Some(lir::compile::Emit::Asm),
+                Emit::Obj => Some(lir::compile::Emit::Obj),
+                Emit::Exe => Some(lir::compile::Emit::Exe),
+            })
+            .collect()
     };
 
     let config = lir::compile::Config {
-        mode: if args.eval {
-            lir::compile::Mode::Jit
-        } else {
-            lir::compile::Mode::Aot
+        mode: match args.print {
+            Some(depth) => lir::compile::Mode::Print { depth },
+            None if args.eval => lir::compile::Mode::Jit,
+            None => lir::compile::Mode::Aot,
         },
-        opt_level: match args.optimize {
-            0 => lir::compile::OptLevel::O0,
-            1 => lir::compile::OptLevel::O1,
-            2 => lir::compile::OptLevel::O2,
-            3 => lir::compile::OptLevel::O3,
+        opt_level: match args.optimize.as_str() {
+            "0" => lir::compile::OptLevel::O0,
+            "1" => lir::compile::OptLevel::O1,
+            "2" => lir::compile::OptLevel::O2,
+            "3" => lir::compile::OptLevel::O3,
+            "s" => lir::compile::OptLevel::Os,
+            "z" => lir::compile::OptLevel::Oz,
             _ => panic!(),
         },
+        passes: args.passes,
+        emit,
+        emit_unoptimized: args.emit_unoptimized,
+        output,
+        target: args.target,
+        cpu: args.cpu,
+        features: args.features,
+        debug: args.debug.then(|| lir::compile::Source {
+            path: file.into(),
+            text: input.clone(),
+        }),
+    };
+
+    match lir::compile::compile(&program, config) {
+        Ok(lir::compile::Output::ExitCode(n)) => n,
+        Ok(lir::compile::Output::Term(value)) => {
+            println!("{}", value);
+            0
+        }
+        Ok(lir::compile::Output::Binary) => 0,
+        Err(error) => {
+            let code = match error {
+                lir::compile::CompileError::Target(_) | lir::compile::CompileError::Passes(_) => 1,
+                lir::compile::CompileError::Verify(..) => 5,
+                lir::compile::CompileError::Write { .. } | lir::compile::CompileError::Link(_) => 6,
+                _ => 4,
+            };
+            report::print_diagnostics(file, &input, &[error.into()]);
+            code
+        }
+    }
+}
+
+/// Evaluates a program with the reference interpreter, reporting the result
+/// like the JIT.
+fn interp(file: &str, input: &str, program: &lir::Program, print: Option<Option<usize>>) -> i32 {
+    let mode = match print {
+        Some(depth) => lir::compile::Mode::Print { depth },
+        None => lir::compile::Mode::Jit,
+    };
+    match lir::interp::interp(program, &mode) {
+        Ok(lir::compile::Output::ExitCode(n)) => n,
+        Ok(lir::compile::Output::Term(value)) => {
+            println!("{}", value);
+            0
+        }
+        Ok(lir::compile::Output::Binary) => unreachable!(),
+        Err(error) => {
+            let code = match error {
+                lir::interp::InterpError::Todo { .. } => 3,
+                lir::interp::InterpError::Undefined { .. } => 7,
+                _ => 4,
+            };
+            report::print_diagnostics(file, input, &[error.into()]);
+            code
+        }
+    }
+}
+
+/// Parses a program and runs the checks that don't need `main`, lowering it
+/// to LIR if it is MIR. Diagnostics are printed and the error is an exit code.
+///
+/// Globals that are defined more than once are reported at the level of
+/// `duplicates`, or not at all without one.
+fn lower(
+    file: &str,
+    input: &str,
+    mir: bool,
+    deny_non_exhaustive: bool,
+    duplicates: Option<report::Level>,
+) -> Result<lir::Program, i32> {
+    let level = duplicates.unwrap_or(report::Level::Warning);
+    let check_duplicates = |diagnostics: &[report::Diagnostic]| {
+        if duplicates.is_some() {
+            report::print_diagnostics(file, input, diagnostics);
+            if report::has_errors(diagnostics) {
+                return Err(2);
+            }
+        }
+        Ok(())
+    };
+
+    let program = if mir {
+        let (program, diagnostics) = match mir::parse::parse_defs(input) {
+            Ok(defs) => mir::parse::collect(defs, level),
+            Err(errors) => {
+                report::print_parse_errors(file, input, errors);
+                return Err(2);
+            }
+        };
+        check_duplicates(&diagnostics)?;
+
+        let diagnostics = mir::arity::check(&program);
+        report::print_diagnostics(file, input, &diagnostics);
+        if report::has_errors(&diagnostics) {
+            return Err(2);
+        }
+
+        let diagnostics = mir::exhaustive::check(&program, deny_non_exhaustive);
+        report::print_diagnostics(file, input, &diagnostics);
+        if report::has_errors(&diagnostics) {
+            return Err(2);
+        }
+
+        let diagnostics = mir::types::check(&program);
+        report::print_diagnostics(file, input, &diagnostics);
+        if report::has_errors(&diagnostics) {
+            return Err(2);
+        }
+
+        mir::compile::compile(program)
+    } else {
+        let (program, diagnostics) = match lir::parse::parse_globals(input) {
+            Ok(globals) => lir::parse::collect(globals, level),
+            Err(errors) => {
+                report::print_parse_errors(file, input, errors);
+                return Err(2);
+            }
+        };
+        check_duplicates(&diagnostics)?;
+        program
     };
 
-    let output = lir::compile::compile(&program, config);
-    match output {
-        lir::compile::Output::ExitCode(n) => n,
-        lir::compile::Output::Binary => 0,
+    let (_, diagnostics) = lir::symbol::intern(&program);
+    report::print_diagnostics(file, input, &diagnostics);
+    if report::has_errors(&diagnostics) {
+        return Err(2);
+    }
+
+    Ok(program)
+}
+
+/// Runs the checks that need a whole program.
+fn check(
+    file: &str,
+    input: &str,
+    program: &lir::Program,
+    check_ownership: bool,
+) -> Result<(), i32> {
+    let diagnostics = lir::validate::check(program);
+    report::print_diagnostics(file, input, &diagnostics);
+    if report::has_errors(&diagnostics) {
+        return Err(2);
+    }
+
+    if check_ownership {
+        let diagnostics = lir::ownership::check(program);
+        report::print_diagnostics(file, input, &diagnostics);
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_print() {
+        let args = Args::try_parse_from(["yoke", "--print", "file.lir"]).unwrap();
+        assert_eq!(args.print, Some(None));
+        assert_eq!(args.input.as_deref(), Some("file.lir"));
+
+        let args = Args::try_parse_from(["yoke", "--interp", "--print", "file.lir"]).unwrap();
+        assert!(args.interp);
+        assert_eq!(args.print, Some(None));
+        assert_eq!(args.input.as_deref(), Some("file.lir"));
+
+        let args = Args::try_parse_from(["yoke", "--print=3", "file.lir"]).unwrap();
+        assert_eq!(args.print, Some(Some(3)));
+        assert_eq!(args.input.as_deref(), Some("file.lir"));
+
+        let args = Args::try_parse_from(["yoke", "file.lir", "-p=3"]).unwrap();
+        assert_eq!(args.print, Some(Some(3)));
+    }
+
     #[test]
     fn test_map_not_maybe_bool() {
         assert_eq!(
             compile(Args {
-                input: "../examples/map-not-maybe-bool.lir".to_string(),
+                command: None,
+                input: Some("../examples/map-not-maybe-bool.lir".to_string()),
                 code: false,
+                mir: false,
+                deny_non_exhaustive: false,
+                deny_duplicates: false,
+                check_ownership: false,
                 eval: true,
-                optimize: 0,
+                print: None,
+                interp: false,
+                output: None,
+                emit: vec![],
+                emit_unoptimized: false,
+                target: None,
+                cpu: None,
+                features: None,
+                optimize: "0".to_string(),
+                passes: None,
+                debug: false,
             }),
             10
         );
     }
+
+    #[test]
+    fn test_map_not_maybe_bool_mir() {
+        assert_eq!(
+            compile(Args {
+                command: None,
+                input: Some("../examples/map-not-maybe-bool.mir".to_string()),
+                code: false,
+                mir: false,
+                deny_non_exhaustive: false,
+                deny_duplicates: false,
+                check_ownership: false,
+                eval: true,
+                print: None,
+                interp: false,
+                output: None,
+                emit: vec![],
+                emit_unoptimized: false,
+                target: None,
+                cpu: None,
+                features: None,
+                optimize: "0".to_string(),
+                passes: None,
+                debug: false,
+            }),
+            1
+        );
+    }
+
+    #[test]
+    fn test_emit() {
+        use inkwell::{context::Context, memory_buffer::MemoryBuffer, module::Module};
+
+        for emit_unoptimized in [false, true] {
+            let output = std::env::temp_dir().join(format!(
+                "yoke-emit-{}-{}",
+                std::process::id(),
+                emit_unoptimized
+            ));
+            assert_eq!(
+                compile(Args {
+                    command: None,
+                    input: Some("../examples/map-not-maybe-bool.lir".to_string()),
+                    code: false,
+                    mir: false,
+                    deny_non_exhaustive: false,
+                    deny_duplicates: false,
+                    check_ownership: false,
+                    eval: false,
+                    print: None,
+                    interp: false,
+                    output: Some(output.to_str().unwrap().to_string()),
+                    emit: vec![Emit::Lir, Emit::LlvmIr, Emit::LlvmBc, Emit::Asm, Emit::Obj],
+                    emit_unoptimized,
+                    target: None,
+                    cpu: None,
+                    features: None,
+                    optimize: "2".to_string(),
+                    passes: None,
+                    debug: false,
+                }),
+                0
+            );
+
+            let read = |extension| {
+                let path = output.with_extension(extension);
+                let contents = std::fs::read(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                contents
+            };
+            let lir = String::from_utf8(read("lir")).unwrap();
+            assert!(lir::parse::parse(&lir).is_ok());
+
+            let context = Context::create();
+            let ir = MemoryBuffer::create_from_memory_range_copy(&read("ll"), "ll");
+            assert!(context.create_module_from_ir(ir).is_ok());
+            let bitcode = MemoryBuffer::create_from_memory_range_copy(&read("bc"), "bc");
+            assert!(Module::parse_bitcode_from_buffer(&bitcode, &context).is_ok());
+            let asm = String::from_utf8(read("s")).unwrap();
+            assert!(asm.contains("main:"));
+            let object = MemoryBuffer::create_from_memory_range_copy(&read("o"), "o");
+            assert!(object.create_object_file().is_ok());
+        }
+    }
 }
@@ -0,0 +1,176 @@
+use super::*;
+use crate::lir::Arity;
+use crate::report::{arguments, Diagnostic, Level};
+use std::collections::BTreeMap;
+
+/// Infers the arity of every constructor from its first use in the source.
+pub fn infer(program: &Program) -> BTreeMap<String, Arity> {
+    first_uses(program)
+        .into_iter()
+        .map(|(ctor, (arity, _))| (ctor, arity))
+        .collect()
+}
+
+/// The arity of every constructor along with the span of its first use.
+pub fn first_uses(program: &Program) -> BTreeMap<String, (Arity, Span)> {
+    let mut first = BTreeMap::new();
+    for (ctor, arity, span) in uses(program) {
+        first.entry(ctor).or_insert((arity, span));
+    }
+    first
+}
+
+/// Reports constructors used with different numbers of arguments and
+/// definitions whose cases have different numbers of patterns.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    let mut names = program.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let switch = &program[name];
+        let Case(pats, _, span) = &switch[0];
+        let arity = pats.len();
+        let mismatches = switch
+            .iter()
+            .filter(|Case(pats, ..)| pats.len() != arity)
+            .collect::<Vec<_>>();
+        if mismatches.is_empty() {
+            continue;
+        }
+        let mut diagnostic = Diagnostic::new(
+            Level::Error,
+            format!("cases of {} have different numbers of patterns", name),
+        )
+        .with_label(span.clone(), format!("{} here", patterns(arity)));
+        for Case(pats, _, span) in mismatches {
+            diagnostic =
+                diagnostic.with_label(span.clone(), format!("{} here", patterns(pats.len())));
+        }
+        diagnostics.push(diagnostic);
+    }
+
+    let mut first = BTreeMap::<String, (Arity, Span)>::new();
+    let mut mismatches = BTreeMap::<String, Vec<(Arity, Span)>>::new();
+    for (ctor, arity, span) in uses(program) {
+        match first.get(&ctor) {
+            None => {
+                first.insert(ctor, (arity, span));
+            }
+            Some((first_arity, _)) if *first_arity != arity => {
+                mismatches.entry(ctor).or_default().push((arity, span));
+            }
+            Some(_) => {}
+        }
+    }
+    for (ctor, mismatches) in mismatches {
+        let (arity, span) = &first[&ctor];
+        let mut diagnostic = Diagnostic::new(
+            Level::Error,
+            format!(
+                "constructor {} is used with different numbers of arguments",
+                ctor
+            ),
+        )
+        .with_label(
+            span.clone(),
+            format!("used with {} here", arguments(*arity as usize)),
+        );
+        for (arity, span) in mismatches {
+            diagnostic = diagnostic.with_label(
+                span,
+                format!("used with {} here", arguments(arity as usize)),
+            );
+        }
+        diagnostics.push(diagnostic);
+    }
+
+    diagnostics
+}
+
+/// Every use of a constructor with its number of arguments and its span, in
+/// source order.
+fn uses(program: &Program) -> Vec<(String, Arity, Span)> {
+    let mut uses = vec![];
+    for switch in program.values() {
+        for Case(pats, term, _) in switch {
+            pats.iter().for_each(|pat| pat_uses(pat, &mut uses));
+            term_uses(term, &mut uses);
+        }
+    }
+    uses.sort_by_key(|(_, _, span)| span.start);
+    uses
+}
+
+fn pat_uses(pat: &Pat, uses: &mut Vec<(String, Arity, Span)>) {
+    if let Pat::Ctor(ctor, pats, span) = pat {
+        uses.push((ctor.clone(), pats.len() as Arity, span.clone()));
+        pats.iter().for_each(|pat| pat_uses(pat, uses));
+    }
+}
+
+fn term_uses(term: &Term, uses: &mut Vec<(String, Arity, Span)>) {
+    match term {
+        Term::Var(..) => {}
+        Term::Ctor(ctor, terms, span) => {
+            uses.push((ctor.clone(), terms.len() as Arity, span.clone()));
+            terms.iter().for_each(|term| term_uses(term, uses));
+        }
+        Term::App(_, terms, _) => terms.iter().for_each(|term| term_uses(term, uses)),
+    }
+}
+
+fn patterns(n: usize) -> String {
+    match n {
+        1 => "1 pattern".to_string(),
+        n => format!("{} patterns", n),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_infer() {
+        let program =
+            parse::parse("main = -> f (Just True) Nil; f = Just x, y -> Cons x y;").unwrap();
+        assert_eq!(check(&program), vec![]);
+        assert_eq!(
+            infer(&program),
+            BTreeMap::from([
+                ("Cons".to_string(), 2),
+                ("Just".to_string(), 1),
+                ("Nil".to_string(), 0),
+                ("True".to_string(), 0),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ctor_mismatch() {
+        assert_eq!(
+            check(&parse::parse("f = Just x -> Just | Nothing -> Just x y;").unwrap()),
+            vec![Diagnostic::new(
+                Level::Error,
+                "constructor Just is used with different numbers of arguments"
+            )
+            .with_label(4..10, "used with 1 argument here")
+            .with_label(14..18, "used with 0 arguments here")
+            .with_label(32..40, "used with 2 arguments here")]
+        );
+    }
+
+    #[test]
+    fn test_case_mismatch() {
+        assert_eq!(
+            check(&parse::parse("f = x, y -> x | x -> x;").unwrap()),
+            vec![Diagnostic::new(
+                Level::Error,
+                "cases of f have different numbers of patterns"
+            )
+            .with_label(4..13, "2 patterns here")
+            .with_label(16..22, "1 pattern here")]
+        );
+    }
+}
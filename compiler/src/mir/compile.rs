@@ -1,6 +1,392 @@
 use super::*;
-use crate::lir;
+use crate::lir::{self, Arity, Block, Global, Index, Name, Op};
+use decision::{Occurrence, Tree};
+use std::collections::BTreeMap;
 
+/// Lowers a program to LIR.
+///
+/// The program must have passed [`arity::check`] and [`types::check`], since
+/// definitions with different numbers of patterns and unknown functions can't
+/// be lowered.
 pub fn compile(program: Program) -> lir::Program {
-    todo!()
+    let ctors = arity::first_uses(&program);
+    let funs = program
+        .iter()
+        .map(|(name, switch)| (name.clone(), arity(name, switch)))
+        .collect::<HashMap<_, _>>();
+
+    let mut lir = ctors
+        .iter()
+        .map(|(name, (arity, span))| {
+            let ctor = Global::Ctor {
+                arity: *arity,
+                symbol: None,
+                span: span.clone(),
+            };
+            (name.clone(), ctor)
+        })
+        .collect::<lir::Program>();
+
+    for (name, switch) in &program {
+        let mut lower = Lower {
+            ctors: &ctors,
+            funs: &funs,
+            main: name == "main",
+            next: 0,
+        };
+        let arity = funs[name];
+        let span = switch[0].2.start..switch[switch.len() - 1].2.end;
+        let block = lower.define(switch, arity, &span);
+        lir.insert(name.clone(), Global::Fun { arity, block, span });
+    }
+
+    lir
+}
+
+fn arity(name: &str, switch: &Switch) -> Arity {
+    let arity = switch[0].0.len();
+    debug_assert!(
+        switch.iter().all(|Case(pats, ..)| pats.len() == arity),
+        "cases of {} have different numbers of patterns",
+        name
+    );
+    arity as Arity
+}
+
+struct Lower<'a> {
+    ctors: &'a BTreeMap<String, (Arity, Span)>,
+    funs: &'a HashMap<String, Arity>,
+    main: bool,
+    next: usize,
+}
+
+type Env = HashMap<String, Name>;
+
+type Locals = HashMap<Occurrence, Name>;
+
+impl Lower<'_> {
+    /// Ops that come from matching rather than from a term are given the
+    /// span of the whole definition.
+    fn define(&mut self, switch: &Switch, arity: Arity, span: &Span) -> Block {
+        let mut block = Block::new();
+        let mut locals = HashMap::new();
+        for index in 0..arity as Index {
+            let name = self.fresh();
+            block.push(Op::LoadArg {
+                name: name.clone(),
+                var: "self".to_string(),
+                index,
+                span: span.clone(),
+            });
+            locals.insert(vec![index], name);
+        }
+        block.extend(self.tree(&decision::compile(switch), switch, &locals, span));
+        block
+    }
+
+    /// Lowers a decision tree to nested switches. Each switch lists the
+    /// constructors the decision tree tests for, and the rest share a `_` case,
+    /// so the default tree is only lowered once.
+    fn tree(&mut self, tree: &Tree, switch: &Switch, locals: &Locals, span: &Span) -> Block {
+        match tree {
+            Tree::Fail => vec![Op::Todo { span: span.clone() }],
+            Tree::Leaf { case, bindings } => {
+                let env = bindings
+                    .iter()
+                    .map(|(var, occurrence)| (var.clone(), locals[occurrence].clone()))
+                    .collect();
+                self.body(&switch[*case].1, &env)
+            }
+            Tree::Switch {
+                occurrence,
+                cases,
+                default,
+            } => {
+                let local = &locals[occurrence];
+                let mut cases = cases
+                    .iter()
+                    .map(|(global, arity, tree)| {
+                        let mut block = Block::new();
+                        let mut locals = locals.clone();
+                        for index in 0..*arity as Index {
+                            let name = self.fresh();
+                            block.push(Op::LoadArg {
+                                name: name.clone(),
+                                var: local.clone(),
+                                index,
+                                span: span.clone(),
+                            });
+                            let mut sub_occurrence = occurrence.clone();
+                            sub_occurrence.push(index);
+                            locals.insert(sub_occurrence, name);
+                        }
+                        block.extend(self.tree(tree, switch, &locals, span));
+                        lir::Case {
+                            global: global.clone(),
+                            block,
+                            span: span.clone(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if cases.len() < self.ctors.len() {
+                    cases.push(lir::Case {
+                        global: "_".to_string(),
+                        block: self.tree(default, switch, locals, span),
+                        span: span.clone(),
+                    });
+                }
+
+                vec![
+                    Op::Eval {
+                        var: local.clone(),
+                        span: span.clone(),
+                    },
+                    Op::Switch {
+                        var: local.clone(),
+                        cases,
+                        span: span.clone(),
+                    },
+                ]
+            }
+        }
+    }
+
+    fn body(&mut self, term: &Term, env: &Env) -> Block {
+        let mut block = Block::new();
+        let var = self.term(term, env, &mut block);
+        let span = term.span().clone();
+        if self.main {
+            block.push(Op::Eval {
+                var: var.clone(),
+                span: span.clone(),
+            });
+            block.push(Op::ReturnSymbol { var, span });
+        } else {
+            if !matches!(term, Term::Ctor(..)) {
+                block.push(Op::Eval {
+                    var: var.clone(),
+                    span: span.clone(),
+                });
+            }
+            block.push(Op::Return { var, span });
+        }
+        block
+    }
+
+    /// Builds a term without evaluating it and returns the local holding it.
+    fn term(&mut self, term: &Term, env: &Env, block: &mut Block) -> Name {
+        match term {
+            Term::Var(var, span) => match env.get(var) {
+                Some(local) => local.clone(),
+                None => self.global(var, vec![], span, block),
+            },
+            Term::Ctor(ctor, terms, span) => {
+                let args = self.terms(terms, env, block);
+                block.push(Op::LoadGlobal {
+                    global: ctor.clone(),
+                    span: span.clone(),
+                });
+                if args.is_empty() {
+                    return ctor.clone();
+                }
+                let name = self.fresh();
+                block.push(Op::NewApp {
+                    name: name.clone(),
+                    var: ctor.clone(),
+                    args,
+                    span: span.clone(),
+                });
+                name
+            }
+            Term::App(var, terms, span) => {
+                let args = self.terms(terms, env, block);
+                match env.get(var) {
+                    Some(local) => {
+                        block.push(Op::Eval {
+                            var: local.clone(),
+                            span: span.clone(),
+                        });
+                        let fun = self.fresh();
+                        block.push(Op::Copy {
+                            name: fun.clone(),
+                            var: local.clone(),
+                            span: span.clone(),
+                        });
+                        self.apply(fun, args, span, block)
+                    }
+                    None => self.global(var, args, span, block),
+                }
+            }
+        }
+    }
+
+    fn terms(&mut self, terms: &[Term], env: &Env, block: &mut Block) -> Vec<Name> {
+        terms
+            .iter()
+            .map(|term| self.term(term, env, block))
+            .collect()
+    }
+
+    /// Applies a global function to some arguments. Over-applied functions
+    /// are evaluated eagerly so that the remaining arguments can be applied
+    /// to the partial application they return.
+    fn global(&mut self, fun: &str, mut args: Vec<Name>, span: &Span, block: &mut Block) -> Name {
+        debug_assert!(self.funs.contains_key(fun), "unknown function: {}", fun);
+        let arity = self.funs[fun] as usize;
+
+        block.push(Op::LoadGlobal {
+            global: fun.to_string(),
+            span: span.clone(),
+        });
+
+        if arity == 0 {
+            if args.is_empty() {
+                return fun.to_string();
+            }
+            let head = self.fresh();
+            block.push(Op::Copy {
+                name: head.clone(),
+                var: fun.to_string(),
+                span: span.clone(),
+            });
+            block.push(Op::Eval {
+                var: head.clone(),
+                span: span.clone(),
+            });
+            return self.apply(head, args, span, block);
+        }
+
+        let rest = args.split_off(args.len().min(arity));
+        let name = self.fresh();
+        if args.len() < arity {
+            block.push(Op::NewPartial {
+                name: name.clone(),
+                var: fun.to_string(),
+                args,
+                span: span.clone(),
+            });
+            return name;
+        }
+
+        block.push(Op::NewApp {
+            name: name.clone(),
+            var: fun.to_string(),
+            args,
+            span: span.clone(),
+        });
+        if rest.is_empty() {
+            return name;
+        }
+        block.push(Op::Eval {
+            var: name.clone(),
+            span: span.clone(),
+        });
+        self.apply(name, rest, span, block)
+    }
+
+    fn apply(&mut self, fun: Name, args: Vec<Name>, span: &Span, block: &mut Block) -> Name {
+        let name = self.fresh();
+        block.push(Op::ApplyPartial {
+            name: name.clone(),
+            var: fun,
+            args,
+            span: span.clone(),
+        });
+        name
+    }
+
+    /// Generates a local name that can't be confused with a global, since
+    /// `load_global` defines a local with the same name as the global.
+    fn fresh(&mut self) -> Name {
+        loop {
+            let name = format!("_{}", self.next);
+            self.next += 1;
+            if !self.ctors.contains_key(&name) && !self.funs.contains_key(&name) {
+                return name;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lir::compile::{Config, Output};
+
+    macro_rules! test {
+        ($input:expr, $expected:expr) => {
+            let program = compile(parse::parse($input).unwrap());
+            assert_eq!(lir::validate::check(&program), vec![]);
+            let Ok(Output::ExitCode(result)) = lir::compile::compile(&program, Config::default())
+            else {
+                panic!()
+            };
+            assert_eq!(result, $expected);
+        };
+    }
+
+    #[test]
+    fn test_ctor() {
+        // False = 1, True = 2
+        test!("main = -> True; f = -> False;", 2);
+    }
+
+    #[test]
+    fn test_not() {
+        // False = 1, True = 2
+        test!(
+            "main = -> not True;
+             not = True -> False | False -> True;",
+            1
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        // False = 1, Just = 2, Nothing = 3, True = 4
+        test!(
+            "main = -> f (Just Nothing) False;
+             f = Just (Just x), _ -> x | Just Nothing, True -> Nothing | _, y -> y;",
+            1
+        );
+    }
+
+    #[test]
+    fn test_lazy() {
+        // False = 1, Just = 2, Nothing = 3, True = 4
+        test!(
+            "main = -> f (unwrap Nothing) False;
+             unwrap = Just x -> x;
+             f = _, True -> True | _, x -> x;",
+            1
+        );
+    }
+
+    #[test]
+    fn test_partial() {
+        // False = 1, Just = 2, Nothing = 3, True = 4
+        test!(
+            "main = -> unwrap (map (const False) (Just True));
+             const = x, _ -> x;
+             unwrap = Just x -> x;
+             map = f, Nothing -> Nothing | f, Just x -> Just (f x);",
+            1
+        );
+    }
+
+    #[test]
+    fn test_size() {
+        // Every switch used to list every constructor, with a copy of the
+        // default tree for each one that wasn't tested for.
+        let mut input = "main = -> f A B C D;
+             f = A, A, A, A -> X | x, B, B, B -> Y | x, y, C, C -> Z | x, y, z, D -> W | x, y, z, w -> V;"
+            .to_string();
+        for i in 0..32 {
+            input.push_str(&format!(" c{} = -> C{};", i, i));
+        }
+        let program = compile(parse::parse(&input).unwrap());
+        assert_eq!(lir::validate::check(&program), vec![]);
+        let lines = lir::print::print(&program).lines().count();
+        assert!(lines < 1000, "{} lines", lines);
+    }
 }
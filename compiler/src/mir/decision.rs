@@ -0,0 +1,229 @@
+use super::*;
+use crate::lir::{Arity, Index};
+
+/// A path from a function argument to one of its subterms. The first index is
+/// the argument and each following index selects an argument of a constructor.
+pub type Occurrence = Vec<Index>;
+
+#[derive(Debug, PartialEq)]
+pub enum Tree {
+    /// No case matches.
+    Fail,
+    /// The case at `case` matches, binding its variables to occurrences.
+    Leaf {
+        case: usize,
+        bindings: Vec<(String, Occurrence)>,
+    },
+    /// Evaluate the term at `occurrence` and branch on its constructor. Every
+    /// constructor not listed in `cases` goes to `default`.
+    Switch {
+        occurrence: Occurrence,
+        cases: Vec<(String, Arity, Tree)>,
+        default: Box<Tree>,
+    },
+}
+
+#[derive(Clone)]
+struct Row<'a> {
+    /// `None` is a wildcard introduced by specialising a variable.
+    pats: Vec<Option<&'a Pat>>,
+    bindings: Vec<(String, Occurrence)>,
+    case: usize,
+}
+
+/// Compiles the cases of a definition to a decision tree, following
+/// Maranget's "Compiling Pattern Matching to Good Decision Trees".
+///
+/// The tree always scrutinises the leftmost constructor pattern of the first
+/// remaining case, so a term is only evaluated when the cases would evaluate
+/// it when tried from top to bottom and left to right.
+pub fn compile(switch: &Switch) -> Tree {
+    let arity = switch.first().map_or(0, |Case(pats, ..)| pats.len());
+    let occurrences = (0..arity as Index).map(|index| vec![index]).collect();
+    let rows = switch
+        .iter()
+        .enumerate()
+        .map(|(case, Case(pats, ..))| Row {
+            pats: pats.iter().map(Some).collect(),
+            bindings: vec![],
+            case,
+        })
+        .collect();
+    tree(occurrences, rows)
+}
+
+fn tree(occurrences: Vec<Occurrence>, rows: Vec<Row>) -> Tree {
+    let Some(first) = rows.first() else {
+        return Tree::Fail;
+    };
+
+    let Some(column) = first
+        .pats
+        .iter()
+        .position(|pat| matches!(pat, Some(Pat::Ctor(..))))
+    else {
+        let mut bindings = first.bindings.clone();
+        for (pat, occurrence) in first.pats.iter().zip(&occurrences) {
+            if let Some(Pat::Var(var, _)) = pat {
+                bindings.push((var.clone(), occurrence.clone()));
+            }
+        }
+        return Tree::Leaf {
+            case: first.case,
+            bindings,
+        };
+    };
+
+    let mut ctors = Vec::<(&String, Arity)>::new();
+    for row in &rows {
+        let Some(Pat::Ctor(ctor, pats, _)) = row.pats[column] else {
+            continue;
+        };
+        if !ctors.iter().any(|(name, _)| *name == ctor) {
+            ctors.push((ctor, pats.len() as Arity));
+        }
+    }
+
+    let occurrence = occurrences[column].clone();
+
+    let cases = ctors
+        .into_iter()
+        .map(|(ctor, arity)| {
+            let mut sub_occurrences = occurrences.clone();
+            sub_occurrences.splice(
+                column..column + 1,
+                (0..arity as Index).map(|index| {
+                    let mut sub_occurrence = occurrence.clone();
+                    sub_occurrence.push(index);
+                    sub_occurrence
+                }),
+            );
+            let rows = rows
+                .iter()
+                .filter_map(|row| specialize(row, column, &occurrence, ctor, arity))
+                .collect();
+            (ctor.clone(), arity, tree(sub_occurrences, rows))
+        })
+        .collect();
+
+    let mut default_occurrences = occurrences.clone();
+    default_occurrences.remove(column);
+    let default_rows = rows
+        .iter()
+        .filter_map(|row| default(row, column, &occurrence))
+        .collect();
+
+    Tree::Switch {
+        occurrence,
+        cases,
+        default: Box::new(tree(default_occurrences, default_rows)),
+    }
+}
+
+/// Keeps the rows that match `ctor` in `column`, replacing the column with
+/// the constructor's arguments.
+fn specialize<'a>(
+    row: &Row<'a>,
+    column: usize,
+    occurrence: &Occurrence,
+    ctor: &str,
+    arity: Arity,
+) -> Option<Row<'a>> {
+    let mut row = row.clone();
+    let args = match row.pats[column] {
+        Some(Pat::Ctor(name, pats, _)) if name == ctor => {
+            if pats.len() != arity as usize {
+                panic!("constructor {} is used with different arities", ctor)
+            }
+            pats.iter().map(Some).collect()
+        }
+        Some(Pat::Ctor(..)) => return None,
+        Some(Pat::Var(var, _)) => {
+            row.bindings.push((var.clone(), occurrence.clone()));
+            vec![None; arity as usize]
+        }
+        None => vec![None; arity as usize],
+    };
+    row.pats.splice(column..column + 1, args);
+    Some(row)
+}
+
+/// Keeps the rows that match any constructor in `column`, removing the column.
+fn default<'a>(row: &Row<'a>, column: usize, occurrence: &Occurrence) -> Option<Row<'a>> {
+    let mut row = row.clone();
+    match row.pats.remove(column) {
+        Some(Pat::Ctor(..)) => return None,
+        Some(Pat::Var(var, _)) => row.bindings.push((var.clone(), occurrence.clone())),
+        None => {}
+    }
+    Some(row)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lazy_column() {
+        let program = parse::parse("f = _, True -> True | x, False -> x;").unwrap();
+        assert_eq!(
+            compile(&program["f"]),
+            Tree::Switch {
+                occurrence: vec![1],
+                cases: vec![
+                    (
+                        "True".to_string(),
+                        0,
+                        Tree::Leaf {
+                            case: 0,
+                            bindings: vec![("_".to_string(), vec![0])],
+                        }
+                    ),
+                    (
+                        "False".to_string(),
+                        0,
+                        Tree::Leaf {
+                            case: 1,
+                            bindings: vec![("x".to_string(), vec![0])],
+                        }
+                    ),
+                ],
+                default: Box::new(Tree::Fail),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let program = parse::parse("f = Just (Just x) -> x | y -> y;").unwrap();
+        assert_eq!(
+            compile(&program["f"]),
+            Tree::Switch {
+                occurrence: vec![0],
+                cases: vec![(
+                    "Just".to_string(),
+                    1,
+                    Tree::Switch {
+                        occurrence: vec![0, 0],
+                        cases: vec![(
+                            "Just".to_string(),
+                            1,
+                            Tree::Leaf {
+                                case: 0,
+                                bindings: vec![("x".to_string(), vec![0, 0, 0])],
+                            }
+                        )],
+                        default: Box::new(Tree::Leaf {
+                            case: 1,
+                            bindings: vec![("y".to_string(), vec![0])],
+                        }),
+                    }
+                )],
+                default: Box::new(Tree::Leaf {
+                    case: 1,
+                    bindings: vec![("y".to_string(), vec![0])],
+                }),
+            }
+        );
+    }
+}
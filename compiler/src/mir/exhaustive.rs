@@ -0,0 +1,389 @@
+use super::*;
+use crate::lir::Arity;
+use crate::report::{Diagnostic, Level};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+/// Checks every definition for missing and unreachable cases, following
+/// Maranget's "Warnings for Pattern Matching".
+///
+/// MIR has no data declarations, so constructors are grouped into types by
+/// where they are matched: constructors matched in the same position of the
+/// same definition are assumed to have the same type, and a set of patterns is
+/// only complete when it covers every constructor in that group. A position
+/// where a case has a variable pattern, or a position containing it, can be
+/// given any constructor (see [`types::check`]), so it is never complete.
+pub fn check(program: &Program, deny_non_exhaustive: bool) -> Vec<Diagnostic> {
+    let groups = Groups::new(program);
+    let mut names = program.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let mut diagnostics = vec![];
+    for name in names {
+        let switch = &program[name];
+        let rows = switch
+            .iter()
+            .map(|Case(pats, ..)| pats.iter().map(Pattern::from).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        let arity = rows.first().map_or(0, Vec::len);
+        let positions = (0..arity)
+            .map(|i| vec![(name.clone(), i)])
+            .collect::<Vec<_>>();
+        for (i, row) in rows.iter().enumerate() {
+            if groups.useful(&rows[..i], row, &positions).is_none() {
+                diagnostics.push(
+                    Diagnostic::new(Level::Warning, format!("unreachable case in {}", name))
+                        .with_label(switch[i].2.clone(), "this case is never used"),
+                );
+            }
+        }
+
+        if let Some(witness) = groups.useful(&rows, &vec![Pattern::Wild; arity], &positions) {
+            let level = if deny_non_exhaustive {
+                Level::Error
+            } else {
+                Level::Warning
+            };
+            let span = switch[0].2.start..switch[switch.len() - 1].2.end;
+            let witness = witness
+                .iter()
+                .map(Pattern::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            diagnostics.push(
+                Diagnostic::new(level, format!("non-exhaustive patterns in {}", name))
+                    .with_label(span, format!("pattern {} not covered", witness)),
+            );
+        }
+    }
+    diagnostics
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Wild,
+    Ctor(String, Vec<Pattern>),
+}
+
+impl From<&Pat> for Pattern {
+    fn from(pat: &Pat) -> Self {
+        match pat {
+            Pat::Var(..) => Pattern::Wild,
+            Pat::Ctor(ctor, pats, _) => {
+                Pattern::Ctor(ctor.clone(), pats.iter().map(Self::from).collect())
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wild => write!(f, "_"),
+            Pattern::Ctor(ctor, pats) => {
+                write!(f, "{}", ctor)?;
+                for pat in pats {
+                    match pat {
+                        Pattern::Ctor(_, args) if !args.is_empty() => write!(f, " ({})", pat)?,
+                        _ => write!(f, " {}", pat)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Where a pattern is in a definition: the definition and the index of the
+/// argument, followed by the constructor and index of each argument inside it.
+type Position = Vec<(String, usize)>;
+
+/// Constructors grouped into types, each group sorted by name.
+pub struct Groups {
+    groups: Vec<Vec<(String, Arity)>>,
+    index: HashMap<String, usize>,
+    /// The positions with a variable pattern in them.
+    vars: HashSet<Position>,
+}
+
+impl Groups {
+    pub fn new(program: &Program) -> Self {
+        let arities = arity::infer(program);
+        let mut positions = HashMap::<Position, Vec<String>>::new();
+        let mut vars = HashSet::new();
+        for (name, switch) in program {
+            for Case(pats, ..) in switch {
+                for (i, pat) in pats.iter().enumerate() {
+                    collect(pat, vec![(name.clone(), i)], &mut positions, &mut vars);
+                }
+            }
+        }
+
+        let mut parents = arities
+            .keys()
+            .map(|ctor| (ctor.clone(), ctor.clone()))
+            .collect::<HashMap<_, _>>();
+        for ctors in positions.values() {
+            for ctor in &ctors[1..] {
+                let a = find(&parents, &ctors[0]);
+                let b = find(&parents, ctor);
+                parents.insert(b, a);
+            }
+        }
+
+        let mut roots = HashMap::<String, Vec<(String, Arity)>>::new();
+        for (ctor, arity) in &arities {
+            let root = find(&parents, ctor);
+            roots.entry(root).or_default().push((ctor.clone(), *arity));
+        }
+
+        let mut groups = vec![];
+        let mut index = HashMap::new();
+        for mut group in roots.into_values() {
+            group.sort();
+            for (ctor, _) in &group {
+                index.insert(ctor.clone(), groups.len());
+            }
+            groups.push(group);
+        }
+        Groups {
+            groups,
+            index,
+            vars,
+        }
+    }
+
+    pub fn group(&self, ctor: &str) -> &[(String, Arity)] {
+        &self.groups[self.index[ctor]]
+    }
+
+    /// Whether other constructors than the ones matched can be given to a
+    /// position.
+    fn is_open(&self, position: &Position) -> bool {
+        (1..=position.len()).any(|len| self.vars.contains(&position[..len]))
+    }
+
+    /// Returns an example of values matched by `vector` but not by any of
+    /// `rows`, if there is one. `positions` are where the patterns in
+    /// `vector` are.
+    fn useful(
+        &self,
+        rows: &[Vec<Pattern>],
+        vector: &[Pattern],
+        positions: &[Position],
+    ) -> Option<Vec<Pattern>> {
+        let Some((head, tail)) = vector.split_first() else {
+            return rows.is_empty().then(Vec::new);
+        };
+        let (position, tail_positions) = positions.split_first().unwrap();
+        let specialized = |ctor: &str, arity: usize| {
+            let mut positions = (0..arity)
+                .map(|i| {
+                    let mut position = position.clone();
+                    position.push((ctor.to_string(), i));
+                    position
+                })
+                .collect::<Vec<_>>();
+            positions.extend_from_slice(tail_positions);
+            positions
+        };
+
+        match head {
+            Pattern::Ctor(ctor, args) => {
+                let rows = specialize(rows, ctor, args.len());
+                let mut vector = args.clone();
+                vector.extend_from_slice(tail);
+                let positions = specialized(ctor, args.len());
+                let witness = self.useful(&rows, &vector, &positions)?;
+                Some(rebuild(ctor, args.len(), witness))
+            }
+            Pattern::Wild => {
+                let used = rows
+                    .iter()
+                    .filter_map(|row| match &row[0] {
+                        Pattern::Ctor(ctor, _) => Some(ctor.as_str()),
+                        Pattern::Wild => None,
+                    })
+                    .collect::<Vec<_>>();
+                let group = used.first().map(|ctor| self.group(ctor));
+
+                // A witness with one of the matched constructors is more
+                // helpful than `_`, so they are tried first even when other
+                // constructors can be given.
+                let complete = group
+                    .filter(|group| group.iter().all(|(ctor, _)| used.contains(&ctor.as_str())));
+                if let Some(group) = complete {
+                    let witness = group.iter().find_map(|(ctor, arity)| {
+                        let rows = specialize(rows, ctor, *arity as usize);
+                        let mut vector = vec![Pattern::Wild; *arity as usize];
+                        vector.extend_from_slice(tail);
+                        let positions = specialized(ctor, *arity as usize);
+                        let witness = self.useful(&rows, &vector, &positions)?;
+                        Some(rebuild(ctor, *arity as usize, witness))
+                    });
+                    if witness.is_some() || !self.is_open(position) {
+                        return witness;
+                    }
+                }
+
+                let rows = rows
+                    .iter()
+                    .filter(|row| row[0] == Pattern::Wild)
+                    .map(|row| row[1..].to_vec())
+                    .collect::<Vec<_>>();
+                let witness = self.useful(&rows, tail, tail_positions)?;
+                let missing = group
+                    .and_then(|group| {
+                        group
+                            .iter()
+                            .find(|(ctor, _)| !used.contains(&ctor.as_str()))
+                    })
+                    .map_or(Pattern::Wild, |(ctor, arity)| {
+                        Pattern::Ctor(ctor.clone(), vec![Pattern::Wild; *arity as usize])
+                    });
+                let mut patterns = vec![missing];
+                patterns.extend(witness);
+                Some(patterns)
+            }
+        }
+    }
+}
+
+fn collect(
+    pat: &Pat,
+    position: Position,
+    positions: &mut HashMap<Position, Vec<String>>,
+    vars: &mut HashSet<Position>,
+) {
+    match pat {
+        Pat::Var(..) => {
+            vars.insert(position);
+        }
+        Pat::Ctor(ctor, pats, _) => {
+            positions
+                .entry(position.clone())
+                .or_default()
+                .push(ctor.clone());
+            for (i, pat) in pats.iter().enumerate() {
+                let mut position = position.clone();
+                position.push((ctor.clone(), i));
+                collect(pat, position, positions, vars);
+            }
+        }
+    }
+}
+
+fn find(parents: &HashMap<String, String>, ctor: &str) -> String {
+    let mut ctor = ctor;
+    while parents[ctor] != ctor {
+        ctor = &parents[ctor];
+    }
+    ctor.to_string()
+}
+
+fn specialize(rows: &[Vec<Pattern>], ctor: &str, arity: usize) -> Vec<Vec<Pattern>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut args = match &row[0] {
+                Pattern::Ctor(name, args) if name == ctor => args.clone(),
+                Pattern::Ctor(..) => return None,
+                Pattern::Wild => vec![Pattern::Wild; arity],
+            };
+            args.extend_from_slice(&row[1..]);
+            Some(args)
+        })
+        .collect()
+}
+
+fn rebuild(ctor: &str, arity: usize, mut witness: Vec<Pattern>) -> Vec<Pattern> {
+    let rest = witness.split_off(arity);
+    let mut patterns = vec![Pattern::Ctor(ctor.to_string(), witness)];
+    patterns.extend(rest);
+    patterns
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(input: &str) -> Vec<String> {
+        check(&parse::parse(input).unwrap(), false)
+            .into_iter()
+            .flat_map(|diagnostic| {
+                let labels = diagnostic.labels.into_iter().map(|(_, label)| label);
+                std::iter::once(diagnostic.message).chain(labels)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exhaustive() {
+        assert_eq!(
+            messages(
+                "not = True -> False | False -> True;
+                 map = f, Nothing -> Nothing | f, Just x -> Just (f x);"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_non_exhaustive() {
+        assert_eq!(
+            check(
+                &parse::parse("f = Nothing -> Nothing | Just x -> x; g = Just x -> x;").unwrap(),
+                true
+            ),
+            vec![
+                Diagnostic::new(Level::Error, "non-exhaustive patterns in g")
+                    .with_label(42..53, "pattern Nothing not covered")
+            ]
+        );
+
+        assert_eq!(
+            messages(
+                "f = Nothing, _ -> True | Just True, Nothing -> True | _, Just x -> x;
+                 not = True -> False | False -> True;"
+            ),
+            vec![
+                "non-exhaustive patterns in f",
+                "pattern Just False, Nothing not covered"
+            ]
+        );
+
+        assert_eq!(
+            messages("f = Cons x (Cons y Nil) -> x | Nil -> Nil;"),
+            vec![
+                "non-exhaustive patterns in f",
+                "pattern Cons _ Nil not covered"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        assert_eq!(
+            messages("f = x -> x | True -> False; main = -> True | -> False;"),
+            vec![
+                "unreachable case in f",
+                "this case is never used",
+                "unreachable case in main",
+                "this case is never used"
+            ]
+        );
+
+        // S is never matched alongside Z, but f can still be given it, since
+        // its second case has a variable pattern.
+        assert_eq!(
+            messages("main = -> f (S Z); f = Z -> A | x -> B;"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages("f = Just (Just x) -> x | Just y -> y | Nothing -> Nothing | z -> z;"),
+            Vec::<String>::new()
+        );
+    }
+}
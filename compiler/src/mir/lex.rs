@@ -0,0 +1,71 @@
+use crate::lex::{is_name, LexError, Scanner};
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tok<'input> {
+    Equals,
+    Semicolon,
+    Bar,
+    Comma,
+    Arrow,
+    LParen,
+    RParen,
+    Var(&'input str),
+    Sym(&'input str),
+}
+
+impl Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Equals => write!(f, "="),
+            Tok::Semicolon => write!(f, ";"),
+            Tok::Bar => write!(f, "|"),
+            Tok::Comma => write!(f, ","),
+            Tok::Arrow => write!(f, "->"),
+            Tok::LParen => write!(f, "("),
+            Tok::RParen => write!(f, ")"),
+            Tok::Var(text) | Tok::Sym(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+pub struct Lexer<'input> {
+    scanner: Scanner<'input>,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(input: &'input str) -> Self {
+        Self {
+            scanner: Scanner::new(input),
+        }
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Result<(usize, Tok<'input>, usize), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = match self.scanner.next_token() {
+            Ok(c) => c?,
+            Err(error) => return Some(Err(error)),
+        };
+        let (start, text, end) = match c {
+            '=' | ';' | '|' | ',' | '(' | ')' => self.scanner.take(1),
+            '-' if self.scanner.starts_with("->") => self.scanner.take(2),
+            c if c.is_ascii_alphabetic() || c == '_' => self.scanner.take_while(is_name),
+            _ => return Some(Err(self.scanner.invalid())),
+        };
+        let tok = match text {
+            "=" => Tok::Equals,
+            ";" => Tok::Semicolon,
+            "|" => Tok::Bar,
+            "," => Tok::Comma,
+            "->" => Tok::Arrow,
+            "(" => Tok::LParen,
+            ")" => Tok::RParen,
+            _ if c.is_ascii_uppercase() => Tok::Sym(text),
+            _ => Tok::Var(text),
+        };
+        Some(Ok((start, tok, end)))
+    }
+}
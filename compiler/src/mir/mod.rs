@@ -1,24 +1,40 @@
+pub mod arity;
 pub mod compile;
+pub mod decision;
+pub mod exhaustive;
+pub mod lex;
 pub mod parse;
+pub mod types;
 
+pub use crate::report::Span;
+pub use indexmap::IndexMap;
 pub use std::collections::HashMap;
 
-pub type Program = HashMap<String, Switch>;
+/// The definitions of a program, in the order they are defined.
+pub type Program = IndexMap<String, Switch>;
 
 pub type Switch = Vec<Case>;
 
 #[derive(Debug, PartialEq)]
-pub struct Case(Vec<Pat>, Term);
+pub struct Case(Vec<Pat>, Term, Span);
 
 #[derive(Debug, PartialEq)]
 pub enum Pat {
-    Var(String),
-    Ctor(String, Vec<Pat>),
+    Var(String, Span),
+    Ctor(String, Vec<Pat>, Span),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Term {
-    Var(String),
-    Ctor(String, Vec<Term>),
-    App(String, Vec<Term>),
+    Var(String, Span),
+    Ctor(String, Vec<Term>, Span),
+    App(String, Vec<Term>, Span),
+}
+
+impl Term {
+    pub fn span(&self) -> &Span {
+        match self {
+            Term::Var(_, span) | Term::Ctor(_, _, span) | Term::App(_, _, span) => span,
+        }
+    }
 }
@@ -1,9 +1,141 @@
 use super::*;
+use crate::{
+    lex::LexError,
+    report::{Diagnostic, Level},
+};
 use grammar::*;
 use lalrpop_util::{lalrpop_mod, ParseError};
+use lex::{Lexer, Tok};
 
 lalrpop_mod!(grammar, "/mir/grammar.rs");
 
-pub fn parse(input: &str) -> Result<Program, ParseError<usize, Token, &str>> {
-    ProgramParser::new().parse(input)
+pub type Error<'input> = ParseError<usize, Tok<'input>, LexError>;
+
+/// Parses a program. A function that is defined more than once keeps its last
+/// definition, which `parse_defs` and `collect` can report.
+pub fn parse(input: &str) -> Result<Program, Error<'_>> {
+    let (program, _) = collect(parse_defs(input)?, Level::Warning);
+    Ok(program)
+}
+
+/// Parses the definitions of a program and the spans of their names, in the
+/// order they are defined, including any that are defined more than once.
+pub fn parse_defs(input: &str) -> Result<Vec<(String, Span, Switch)>, Error<'_>> {
+    DefsParser::new().parse(Lexer::new(input))
+}
+
+/// Collects definitions into a program in the order they are first defined. A
+/// function that is defined more than once keeps its last definition, and
+/// every definition that replaces another is reported at `level`.
+pub fn collect(defs: Vec<(String, Span, Switch)>, level: Level) -> (Program, Vec<Diagnostic>) {
+    let mut spans = HashMap::<String, Span>::new();
+    let mut program = Program::new();
+    let mut diagnostics = vec![];
+    for (name, span, switch) in defs {
+        if let Some(previous) = spans.insert(name.clone(), span.clone()) {
+            diagnostics.push(
+                Diagnostic::new(
+                    level,
+                    format!("function {} is defined more than once", name),
+                )
+                .with_label(span, "redefined here")
+                .with_label(previous, "previously defined here"),
+            );
+        }
+        program.insert(name, switch);
+    }
+    (program, diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program() {
+        assert_eq!(
+            parse("main = -> True;").unwrap(),
+            Program::from([(
+                "main".to_string(),
+                vec![Case(
+                    vec![],
+                    Term::Ctor("True".to_string(), vec![], 10..14),
+                    7..14
+                )]
+            )])
+        );
+
+        assert_eq!(
+            parse("f = Just x, _ -> g x | _, y -> y;").unwrap(),
+            Program::from([(
+                "f".to_string(),
+                vec![
+                    Case(
+                        vec![
+                            Pat::Ctor(
+                                "Just".to_string(),
+                                vec![Pat::Var("x".to_string(), 9..10)],
+                                4..10
+                            ),
+                            Pat::Var("_".to_string(), 12..13)
+                        ],
+                        Term::App(
+                            "g".to_string(),
+                            vec![Term::Var("x".to_string(), 19..20)],
+                            17..20
+                        ),
+                        4..20
+                    ),
+                    Case(
+                        vec![
+                            Pat::Var("_".to_string(), 23..24),
+                            Pat::Var("y".to_string(), 26..27)
+                        ],
+                        Term::Var("y".to_string(), 31..32),
+                        23..32
+                    ),
+                ]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            parse("-- main\nmain = {- -> False -} -> True; # main").unwrap(),
+            Program::from([(
+                "main".to_string(),
+                vec![Case(
+                    vec![],
+                    Term::Ctor("True".to_string(), vec![], 33..37),
+                    30..37
+                )]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let (program, diagnostics) = collect(
+            parse_defs("f = -> A; g = -> B; f = -> C;").unwrap(),
+            Level::Warning,
+        );
+        assert_eq!(program.keys().collect::<Vec<_>>(), vec!["f", "g"]);
+        assert_eq!(
+            program["f"],
+            vec![Case(
+                vec![],
+                Term::Ctor("C".to_string(), vec![], 27..28),
+                24..28
+            )]
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(Level::Warning, "function f is defined more than once")
+                    .with_label(20..21, "redefined here")
+                    .with_label(0..1, "previously defined here")
+            ]
+        );
+    }
 }
@@ -0,0 +1,664 @@
+use super::*;
+use crate::lir::Arity;
+use crate::report::{Diagnostic, Level};
+use std::collections::{BTreeMap, HashSet};
+
+/// Infers a type for every definition and reports the places where types
+/// don't agree.
+///
+/// Types are structural: a data type is the set of constructors a term may
+/// evaluate to, along with the types of their arguments. Building a
+/// constructor gives an open set, which can grow to include other
+/// constructors. Matching on constructors gives the closed set of the
+/// constructors matched, unless there is a variable pattern in the same
+/// position, so passing a value with a constructor that the definition doesn't
+/// match is an error.
+///
+/// Data types may be recursive, so the occurs check only rejects types that
+/// contain themselves through functions alone, and definitions are generalised
+/// once every definition they depend on has been inferred.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let arities = arity::infer(program);
+    let mut infer = Infer {
+        program,
+        arities: &arities,
+        nodes: vec![],
+        types: HashMap::new(),
+        generic: HashSet::new(),
+        diagnostics: vec![],
+    };
+
+    for names in components(program) {
+        for name in &names {
+            let node = infer.fresh();
+            infer.types.insert(name.to_string(), node);
+        }
+        for name in &names {
+            infer.define(name);
+        }
+        infer.generic.extend(names.into_iter().map(str::to_string));
+    }
+
+    infer.diagnostics
+}
+
+type Type = usize;
+
+/// Constructors and the types of their arguments.
+type Row = BTreeMap<String, Vec<Type>>;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Var,
+    Link(Type),
+    Fun(Type, Type),
+    /// The constructors of a data type and the type of any other
+    /// constructors, which is `None` if the set is closed.
+    Data(Row, Option<Type>),
+}
+
+type Env = HashMap<String, Type>;
+
+struct Infer<'a> {
+    program: &'a Program,
+    arities: &'a BTreeMap<String, Arity>,
+    nodes: Vec<Node>,
+    types: HashMap<String, Type>,
+    generic: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Infer<'_> {
+    fn define(&mut self, name: &str) {
+        let switch = &self.program[name];
+        let arity = switch[0].0.len();
+
+        let mut envs = vec![Env::new(); switch.len()];
+        let params = (0..arity)
+            .map(|i| {
+                let pats = switch
+                    .iter()
+                    .enumerate()
+                    .map(|(row, Case(pats, ..))| (row, &pats[i]))
+                    .collect();
+                self.pattern(pats, false, &mut envs)
+            })
+            .collect::<Vec<_>>();
+
+        let result = self.fresh();
+        if name == "main" {
+            let rest = self.fresh();
+            let data = self.node(Node::Data(BTreeMap::new(), Some(rest)));
+            self.unify_at(result, data, name, &switch[0].2);
+        }
+
+        for (Case(_, term, span), env) in switch.iter().zip(&envs) {
+            if let Some(body) = self.term(term, env, name) {
+                self.unify_at(result, body, name, span);
+            }
+        }
+
+        let fun = params
+            .into_iter()
+            .rev()
+            .fold(result, |result, param| self.node(Node::Fun(param, result)));
+        let def = self.types[name];
+        self.unify_at(def, fun, name, &switch[0].2);
+    }
+
+    /// Gives a type to one position in every case, binding any variables in
+    /// `envs`. A position is open if any case has a variable pattern in it or
+    /// in a position containing it.
+    fn pattern(&mut self, pats: Vec<(usize, &Pat)>, open: bool, envs: &mut [Env]) -> Type {
+        let open = open || pats.iter().any(|(_, pat)| matches!(pat, Pat::Var(..)));
+
+        let mut ctors = Vec::<(&String, Vec<(usize, &Pat)>)>::new();
+        for (row, pat) in &pats {
+            if let Pat::Ctor(ctor, _, _) = pat {
+                match ctors.iter_mut().find(|(name, _)| *name == ctor) {
+                    Some((_, rows)) => rows.push((*row, pat)),
+                    None => ctors.push((ctor, vec![(*row, pat)])),
+                }
+            }
+        }
+
+        let node = if ctors.is_empty() {
+            self.fresh()
+        } else {
+            let mut row = BTreeMap::new();
+            for (ctor, rows) in &ctors {
+                let arity = self.arities[ctor.as_str()] as usize;
+                let args = (0..arity)
+                    .map(|i| {
+                        let pats = rows
+                            .iter()
+                            .filter_map(|(row, pat)| match pat {
+                                Pat::Ctor(_, args, _) => args.get(i).map(|arg| (*row, arg)),
+                                Pat::Var(..) => None,
+                            })
+                            .collect();
+                        self.pattern(pats, open, envs)
+                    })
+                    .collect();
+                row.insert(ctor.to_string(), args);
+            }
+
+            let rest = open.then(|| self.fresh());
+            self.node(Node::Data(row, rest))
+        };
+
+        for (row, pat) in pats {
+            if let Pat::Var(var, _) = pat {
+                envs[row].insert(var.clone(), node);
+            }
+        }
+        node
+    }
+
+    fn term(&mut self, term: &Term, env: &Env, name: &str) -> Option<Type> {
+        match term {
+            Term::Var(var, span) => self.var(var, env, name, span),
+            Term::Ctor(ctor, terms, _) => {
+                let args = terms
+                    .iter()
+                    .map(|term| self.term(term, env, name))
+                    .collect::<Option<Vec<_>>>()?;
+                let rest = self.fresh();
+                Some(self.node(Node::Data(
+                    BTreeMap::from([(ctor.clone(), args)]),
+                    Some(rest),
+                )))
+            }
+            Term::App(var, terms, span) => {
+                let fun = self.var(var, env, name, span)?;
+                let args = terms
+                    .iter()
+                    .map(|term| self.term(term, env, name))
+                    .collect::<Option<Vec<_>>>()?;
+                let result = self.fresh();
+                let app = args
+                    .into_iter()
+                    .rev()
+                    .fold(result, |result, arg| self.node(Node::Fun(arg, result)));
+                self.unify_at(fun, app, name, span).then_some(result)
+            }
+        }
+    }
+
+    fn var(&mut self, var: &str, env: &Env, name: &str, span: &Span) -> Option<Type> {
+        if let Some(local) = env.get(var) {
+            return Some(*local);
+        }
+        match self.types.get(var) {
+            Some(global) if self.generic.contains(var) => {
+                Some(self.instantiate(*global, &mut HashMap::new()))
+            }
+            Some(global) => Some(*global),
+            None => {
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        Level::Error,
+                        format!("unknown variable {} in {}", var, name),
+                    )
+                    .with_label(span.clone(), format!("{} is not defined", var)),
+                );
+                None
+            }
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.node(Node::Var)
+    }
+
+    fn node(&mut self, node: Node) -> Type {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn find(&self, mut ty: Type) -> Type {
+        while let Node::Link(next) = self.nodes[ty] {
+            ty = next;
+        }
+        ty
+    }
+
+    /// Copies a generalised type. Every variable in it is generic, since the
+    /// original is never unified with anything.
+    fn instantiate(&mut self, ty: Type, copies: &mut HashMap<Type, Type>) -> Type {
+        let ty = self.find(ty);
+        if let Some(copy) = copies.get(&ty) {
+            return *copy;
+        }
+        let copy = self.fresh();
+        copies.insert(ty, copy);
+        self.nodes[copy] = match self.nodes[ty].clone() {
+            Node::Var | Node::Link(_) => Node::Var,
+            Node::Fun(param, result) => Node::Fun(
+                self.instantiate(param, copies),
+                self.instantiate(result, copies),
+            ),
+            Node::Data(row, rest) => Node::Data(
+                row.into_iter()
+                    .map(|(ctor, args)| {
+                        let args = args
+                            .into_iter()
+                            .map(|arg| self.instantiate(arg, copies))
+                            .collect();
+                        (ctor, args)
+                    })
+                    .collect(),
+                rest.map(|rest| self.instantiate(rest, copies)),
+            ),
+        };
+        copy
+    }
+
+    fn unify_at(&mut self, a: Type, b: Type, name: &str, span: &Span) -> bool {
+        match self.unify(a, b) {
+            Ok(()) => true,
+            Err(message) => {
+                self.diagnostics.push(
+                    Diagnostic::new(Level::Error, format!("type error in {}", name))
+                        .with_label(span.clone(), message),
+                );
+                false
+            }
+        }
+    }
+
+    /// Unifies two types. The types are linked before their parts are
+    /// unified, so that recursive types terminate.
+    fn unify(&mut self, a: Type, b: Type) -> Result<(), String> {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a == b {
+            return Ok(());
+        }
+
+        if self.occurs(a, b) || self.occurs(b, a) {
+            return Err("infinite type: a function that contains itself".to_string());
+        }
+
+        match (self.nodes[a].clone(), self.nodes[b].clone()) {
+            (Node::Var, _) => {
+                self.nodes[a] = Node::Link(b);
+                Ok(())
+            }
+            (_, Node::Var) => {
+                self.nodes[b] = Node::Link(a);
+                Ok(())
+            }
+            (Node::Fun(a_param, a_result), Node::Fun(b_param, b_result)) => {
+                self.nodes[a] = Node::Link(b);
+                self.unify(a_param, b_param)?;
+                self.unify(a_result, b_result)
+            }
+            (Node::Data(..), Node::Data(..)) => {
+                let (a_row, a_rest) = self.flatten(a)?;
+                let (b_row, b_rest) = self.flatten(b)?;
+                self.nodes[a] = Node::Link(b);
+
+                for (ctor, a_args) in &a_row {
+                    if let Some(b_args) = b_row.get(ctor) {
+                        for (a_arg, b_arg) in a_args.iter().zip(b_args) {
+                            self.unify(*a_arg, *b_arg)?;
+                        }
+                    }
+                }
+
+                let only_a = difference(&a_row, &b_row);
+                let only_b = difference(&b_row, &a_row);
+                if a_rest.is_some() && a_rest == b_rest && !(only_a.is_empty() && only_b.is_empty())
+                {
+                    return Err(format!(
+                        "constructors {} and {} can't both be in the same type",
+                        names(&only_a),
+                        names(&only_b)
+                    ));
+                }
+
+                match (only_a.is_empty(), only_b.is_empty()) {
+                    (true, true) => match (a_rest, b_rest) {
+                        (Some(a_rest), Some(b_rest)) => self.unify(a_rest, b_rest),
+                        (Some(rest), None) | (None, Some(rest)) => {
+                            self.nodes[rest] = Node::Data(BTreeMap::new(), None);
+                            Ok(())
+                        }
+                        (None, None) => Ok(()),
+                    },
+                    (false, true) => self.extend(b_rest, only_a, a_rest, &b_row),
+                    (true, false) => self.extend(a_rest, only_b, b_rest, &a_row),
+                    (false, false) => {
+                        let rest = self.fresh();
+                        self.extend(a_rest, only_b, Some(rest), &a_row)?;
+                        self.extend(b_rest, only_a, Some(rest), &b_row)
+                    }
+                }
+            }
+            (a, b) => Err(format!(
+                "mismatched types: {} and {}",
+                self.describe(&a),
+                self.describe(&b)
+            )),
+        }
+    }
+
+    /// Whether `ty` is a function containing `var` through functions alone.
+    /// Data types can contain themselves, so they aren't searched.
+    fn occurs(&self, var: Type, ty: Type) -> bool {
+        let ty = self.find(ty);
+        match self.nodes[ty] {
+            Node::Fun(param, result) => {
+                let (param, result) = (self.find(param), self.find(result));
+                param == var || result == var || self.occurs(var, param) || self.occurs(var, result)
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds constructors to an open data type by binding its rest.
+    fn extend(
+        &mut self,
+        rest: Option<Type>,
+        ctors: Row,
+        new_rest: Option<Type>,
+        row: &Row,
+    ) -> Result<(), String> {
+        match rest {
+            Some(rest) => {
+                let data = self.node(Node::Data(ctors, new_rest));
+                self.unify(rest, data)
+            }
+            None => Err(format!(
+                "constructor {} is not one of {}",
+                ctors.keys().next().unwrap(),
+                names(row)
+            )),
+        }
+    }
+
+    /// Collects the constructors of a data type, following its rest until
+    /// reaching a variable or the end of a closed set.
+    fn flatten(&self, ty: Type) -> Result<(Row, Option<Type>), String> {
+        let mut row = BTreeMap::new();
+        let mut seen = HashSet::new();
+        let mut ty = self.find(ty);
+        loop {
+            if !seen.insert(ty) {
+                return Err("infinite set of constructors".to_string());
+            }
+            match &self.nodes[ty] {
+                Node::Var => return Ok((row, Some(ty))),
+                Node::Data(ctors, rest) => {
+                    for (ctor, args) in ctors {
+                        row.entry(ctor.clone()).or_insert_with(|| args.clone());
+                    }
+                    match rest {
+                        Some(rest) => ty = self.find(*rest),
+                        None => return Ok((row, None)),
+                    }
+                }
+                node => {
+                    return Err(format!(
+                        "mismatched types: {} and a constructor",
+                        self.describe(node)
+                    ));
+                }
+            }
+        }
+    }
+
+    fn describe(&self, node: &Node) -> String {
+        match node {
+            Node::Var | Node::Link(_) => "anything".to_string(),
+            Node::Fun(..) => "a function".to_string(),
+            Node::Data(row, _) if row.is_empty() => "a constructor".to_string(),
+            Node::Data(row, _) => format!("one of {}", names(row)),
+        }
+    }
+}
+
+fn difference(a: &Row, b: &Row) -> Row {
+    a.iter()
+        .filter(|(ctor, _)| !b.contains_key(*ctor))
+        .map(|(ctor, args)| (ctor.clone(), args.clone()))
+        .collect()
+}
+
+fn names(row: &Row) -> String {
+    row.keys().cloned().collect::<Vec<_>>().join(", ")
+}
+
+/// Groups the definitions into strongly connected components of the
+/// dependency graph using Tarjan's algorithm. Each component comes after the
+/// components it depends on.
+fn components(program: &Program) -> Vec<Vec<&str>> {
+    let mut names = program.keys().map(String::as_str).collect::<Vec<_>>();
+    names.sort();
+
+    let mut tarjan = Tarjan {
+        program,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: vec![],
+        components: vec![],
+    };
+    for name in names {
+        if !tarjan.index.contains_key(name) {
+            tarjan.visit(name);
+        }
+    }
+    tarjan.components
+}
+
+struct Tarjan<'a> {
+    program: &'a Program,
+    index: HashMap<&'a str, usize>,
+    low: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    components: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, name: &'a str) {
+        let index = self.index.len();
+        self.index.insert(name, index);
+        self.low.insert(name, index);
+        self.stack.push(name);
+
+        let mut deps = vec![];
+        for Case(pats, term, _) in &self.program[name] {
+            let mut bound = HashSet::new();
+            pats.iter().for_each(|pat| pat_vars(pat, &mut bound));
+            term_deps(term, &bound, &mut deps);
+        }
+
+        for dep in deps {
+            let Some((dep, _)) = self.program.get_key_value(dep) else {
+                continue;
+            };
+            let dep = dep.as_str();
+            if !self.index.contains_key(dep) {
+                self.visit(dep);
+                let low = self.low[name].min(self.low[dep]);
+                self.low.insert(name, low);
+            } else if self.stack.contains(&dep) {
+                let low = self.low[name].min(self.index[dep]);
+                self.low.insert(name, low);
+            }
+        }
+
+        if self.low[name] == self.index[name] {
+            let mut component = vec![];
+            loop {
+                let member = self.stack.pop().unwrap();
+                component.push(member);
+                if member == name {
+                    break;
+                }
+            }
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+fn pat_vars<'a>(pat: &'a Pat, bound: &mut HashSet<&'a str>) {
+    match pat {
+        Pat::Var(var, _) => {
+            bound.insert(var);
+        }
+        Pat::Ctor(_, pats, _) => pats.iter().for_each(|pat| pat_vars(pat, bound)),
+    }
+}
+
+fn term_deps<'a>(term: &'a Term, bound: &HashSet<&str>, deps: &mut Vec<&'a str>) {
+    match term {
+        Term::Var(var, _) if !bound.contains(var.as_str()) => deps.push(var),
+        Term::Var(..) => {}
+        Term::Ctor(_, terms, _) => terms.iter().for_each(|term| term_deps(term, bound, deps)),
+        Term::App(var, terms, _) => {
+            if !bound.contains(var.as_str()) {
+                deps.push(var);
+            }
+            terms.iter().for_each(|term| term_deps(term, bound, deps));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(input: &str) -> Vec<String> {
+        check(&parse::parse(input).unwrap())
+            .into_iter()
+            .flat_map(|diagnostic| {
+                let labels = diagnostic.labels.into_iter().map(|(_, label)| label);
+                std::iter::once(diagnostic.message).chain(labels)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_well_typed() {
+        assert_eq!(
+            messages(
+                "main = -> unwrap (map not (Just True));
+                 not = False -> True | True -> False;
+                 unwrap = Just x -> x | Nothing -> False;
+                 map = f, Nothing -> Nothing | f, Just x -> Just (f x);"
+            ),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(
+            messages(
+                "main = -> length (Cons (id True) (Cons (id Z) Nil));
+                 id = x -> x;
+                 length = Nil -> Z | Cons _ xs -> S (length xs);"
+            ),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(
+            messages(
+                "main = -> f True;
+                 f = True -> Nothing | x -> Just x;
+                 g = Nothing -> A | Just _ -> B;
+                 h = x -> g (f x);"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_unmatched_ctor() {
+        assert_eq!(
+            messages(
+                "main = -> unwrap True;
+                 unwrap = Just x -> x;
+                 f = Nothing -> Nothing | Just x -> Just x;"
+            ),
+            vec!["type error in main", "constructor True is not one of Just"]
+        );
+
+        // Only the constructors that unwrap matches are in its type, whatever
+        // other definitions match.
+        assert_eq!(
+            messages("main = -> unwrap Nothing; unwrap = Just x -> x;"),
+            vec![
+                "type error in main",
+                "constructor Nothing is not one of Just"
+            ]
+        );
+        assert_eq!(
+            messages(
+                "main = -> unwrap Nothing;
+                 unwrap = Just x -> x;
+                 f = Nothing -> A | Just x -> B;"
+            ),
+            vec![
+                "type error in main",
+                "constructor Nothing is not one of Just"
+            ]
+        );
+
+        assert_eq!(
+            messages(
+                "main = -> g (not True);
+                 not = False -> True | True -> False;
+                 g = Nothing -> A | Just _ -> B;"
+            ),
+            vec![
+                "type error in main",
+                "constructor False is not one of Just, Nothing"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(
+            messages("main = -> f True; f = g -> g True;"),
+            vec![
+                "type error in main",
+                "mismatched types: a function and one of True"
+            ]
+        );
+
+        assert_eq!(
+            messages("main = -> f; f = x -> x;"),
+            vec![
+                "type error in main",
+                "mismatched types: a constructor and a function"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_occurs() {
+        assert_eq!(
+            messages("f = x -> x x;"),
+            vec![
+                "type error in f",
+                "infinite type: a function that contains itself"
+            ]
+        );
+
+        // Data types can contain themselves.
+        assert_eq!(
+            messages("f = Cons x xs -> Cons x (f xs) | Nil -> Nil;"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_unknown_variable() {
+        assert_eq!(
+            messages("main = -> f True;"),
+            vec!["unknown variable f in main", "f is not defined"]
+        );
+    }
+}
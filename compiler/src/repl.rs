@@ -0,0 +1,226 @@
+use crate::{
+    lex::LexError,
+    lir, mir,
+    report::{self, Level},
+};
+use lalrpop_util::ParseError;
+use std::io::{BufRead, Write};
+
+/// Reads definitions and expressions from stdin until it is closed.
+///
+/// The session is kept as source text, so every input is checked and compiled
+/// along with everything defined before it, and diagnostics can point at any
+/// of it. An expression is compiled as the body of `main`: a term in MIR, or a
+/// list of instructions in LIR. Input that ends before it is complete is
+/// continued on the next line. Defining a global again replaces it.
+///
+/// Expressions are evaluated with the JIT. The RTS exits the process when a
+/// case is missing, so non-exhaustive patterns in MIR are errors, and a `todo`
+/// reached by an LIR expression ends the session.
+pub fn repl(file: Option<String>, mir: bool) -> i32 {
+    let mut repl = Repl {
+        source: String::new(),
+        mir,
+    };
+
+    if let Some(file) = file {
+        repl.mir |= file.ends_with(".mir");
+        repl.source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Failed to read file: {}", e);
+                return 1;
+            }
+        };
+        let duplicates = Some(Level::Warning);
+        if let Err(code) = crate::lower(&file, &repl.source, repl.mir, true, duplicates) {
+            return code;
+        }
+    }
+
+    let mut input = String::new();
+    let mut stdin = std::io::stdin().lock();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { ". " });
+        std::io::stdout().flush().unwrap();
+
+        match stdin.read_line(&mut input) {
+            Ok(0) => return 0,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                return 1;
+            }
+        }
+
+        if input.trim().is_empty() || repl.input(&input) {
+            input.clear();
+        }
+    }
+}
+
+struct Repl {
+    source: String,
+    mir: bool,
+}
+
+/// The session with some input appended, and where the input starts.
+struct Text {
+    text: String,
+    start: usize,
+}
+
+impl Repl {
+    /// Defines or evaluates some input, returning false if it is incomplete.
+    fn input(&mut self, input: &str) -> bool {
+        let definitions = self.append("", input, "");
+        let expression = if self.mir {
+            self.append("main = -> ", input, ";")
+        } else {
+            self.append("main = 0 { ", input, " }")
+        };
+
+        let Some(definitions_error) = self.parse_error(&definitions.text) else {
+            if crate::lower("<repl>", &definitions.text, self.mir, true, None).is_ok() {
+                self.source = definitions.text;
+            }
+            return true;
+        };
+        let Some(expression_error) = self.parse_error(&expression.text) else {
+            if let Some(value) = self.eval(&expression.text) {
+                println!("{}", value);
+            }
+            return true;
+        };
+
+        let definitions_error = definitions_error - definitions.start;
+        let expression_error = expression_error - expression.start;
+        if definitions_error >= input.len() || expression_error >= input.len() {
+            return false;
+        }
+
+        // Report the error from whichever reading of the input got further.
+        let text = if definitions_error >= expression_error {
+            &definitions.text
+        } else {
+            &expression.text
+        };
+        let _ = crate::lower("<repl>", text, self.mir, true, None);
+        true
+    }
+
+    fn append(&self, prefix: &str, input: &str, suffix: &str) -> Text {
+        Text {
+            text: format!("{}\n{}{}{}", self.source, prefix, input, suffix),
+            start: self.source.len() + 1 + prefix.len(),
+        }
+    }
+
+    /// Where parsing fails, if it does.
+    fn parse_error(&self, text: &str) -> Option<usize> {
+        if self.mir {
+            mir::parse::parse(text).err().map(|e| location(&e))
+        } else {
+            lir::parse::parse(text).err().map(|e| location(&e))
+        }
+    }
+
+    /// The term that `main` evaluates to, or `None` if there are errors, which
+    /// are printed.
+    fn eval(&self, text: &str) -> Option<lir::compile::Value> {
+        let program = crate::lower("<repl>", text, self.mir, true, None).ok()?;
+        crate::check("<repl>", text, &program, false).ok()?;
+
+        let config = lir::compile::Config {
+            mode: lir::compile::Mode::Print { depth: None },
+            ..lir::compile::Config::default()
+        };
+        match lir::compile::compile(&program, config) {
+            Ok(lir::compile::Output::Term(value)) => Some(value),
+            Ok(_) => unreachable!(),
+            Err(error) => {
+                report::print_diagnostics("<repl>", text, &[error.into()]);
+                None
+            }
+        }
+    }
+}
+
+/// Running out of input, or ending inside a block comment, counts as failing
+/// after all of it.
+fn location<T>(error: &ParseError<usize, T, LexError>) -> usize {
+    match error {
+        ParseError::InvalidToken { location } => *location,
+        ParseError::UnrecognizedEof { .. } => usize::MAX,
+        ParseError::UnrecognizedToken {
+            token: (start, ..), ..
+        }
+        | ParseError::ExtraToken { token: (start, ..) } => *start,
+        ParseError::User {
+            error: LexError::InvalidToken { location },
+        } => *location,
+        ParseError::User {
+            error: LexError::UnterminatedComment { .. },
+        } => usize::MAX,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_input() {
+        let mut repl = Repl {
+            source: String::new(),
+            mir: true,
+        };
+        assert!(!repl.input("id = x -> x\n"));
+        assert!(repl.input("id = x -> x\n;\n"));
+        assert!(!repl.input("not = True -> False\n"));
+        assert!(repl.input("not = True -> False\n| False -> True;\n"));
+        assert!(repl.input("not = ;\n"));
+        assert_eq!(
+            repl.source,
+            "\nid = x -> x\n;\n\nnot = True -> False\n| False -> True;\n"
+        );
+
+        let mut repl = Repl {
+            source: String::new(),
+            mir: false,
+        };
+        assert!(!repl.input("f = 1 {\n"));
+        assert!(!repl.input("f = 1 {\n  x = load_arg self 0\n"));
+        assert!(repl.input("f = 1 {\n  x = load_arg self 0\n  return x\n}\n"));
+        assert!(!repl.input("switch f {\n"));
+        assert!(repl.input("f = 1 }\n"));
+        assert_eq!(
+            repl.source,
+            "\nf = 1 {\n  x = load_arg self 0\n  return x\n}\n"
+        );
+        assert!(!repl.input("{- f = 1 {\n"));
+        assert!(repl.input("{- f = 1 {\n-} -- f = 1 {\n"));
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut repl = Repl {
+            source: String::new(),
+            mir: true,
+        };
+        // A definition with a missing case is rejected, since reaching it
+        // would end the session.
+        assert!(repl.input("unwrap = Just x -> x;\nf = Nothing -> A | Just x -> B;\n"));
+        assert_eq!(repl.source, "");
+
+        assert!(repl.input("unwrap = Just x -> x | Nothing -> False;\n"));
+        assert_eq!(
+            repl.eval(&repl.append("main = -> ", "unwrap (Just True)", ";").text),
+            Some(lir::compile::Value::Ctor("True".to_string(), vec![]))
+        );
+        assert_eq!(
+            repl.eval(&repl.append("main = -> ", "unwrap True", ";").text),
+            None
+        );
+    }
+}
@@ -1,8 +1,80 @@
+use crate::lex::LexError;
 use ariadne::{Label, Report, ReportKind, Source};
 use lalrpop_util::ParseError;
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
-pub fn print_parse_errors<T: Display>(file: &str, input: &str, error: ParseError<usize, T, &str>) {
+pub type Span = Range<usize>;
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub labels: Vec<(Span, String)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            labels: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push((span, message.into()));
+        self
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.level == Level::Error)
+}
+
+/// Counts arguments for a diagnostic, like "1 argument" or "2 arguments".
+pub fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+pub fn print_diagnostics(file: &str, input: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        let kind = match diagnostic.level {
+            Level::Error => ReportKind::Error,
+            Level::Warning => ReportKind::Warning,
+        };
+        let span = diagnostic
+            .labels
+            .first()
+            .map_or(0..0, |(span, _)| span.clone());
+        let labels = diagnostic
+            .labels
+            .iter()
+            .map(|(span, message)| Label::new((file, span.clone())).with_message(message));
+        Report::build(kind, (file, span))
+            .with_message(&diagnostic.message)
+            .with_labels(labels)
+            .finish()
+            .eprint((file, Source::from(input)))
+            .unwrap();
+    }
+}
+
+pub fn print_parse_errors<T: Display>(
+    file: &str,
+    input: &str,
+    error: ParseError<usize, T, LexError>,
+) {
     match error {
         ParseError::InvalidToken { location } => {
             build_report(file, input, "unrecognized token", location, location)
@@ -37,7 +109,10 @@ pub fn print_parse_errors<T: Display>(file: &str, input: &str, error: ParseError
             start,
             end,
         ),
-        ParseError::User { error } => build_report(file, input, error, 0, 0),
+        ParseError::User { error } => {
+            let span = error.span();
+            build_report(file, input, &error.to_string(), span.start, span.end)
+        }
     }
 }
 
@@ -0,0 +1,218 @@
+//! Runs every program in `examples/` and `tests/`, evaluating it with the JIT
+//! and compiling it ahead of time at every optimization level as well as
+//! with the reference interpreter, and checks what it does against what is
+//! expected of it.
+//!
+//! What is expected of a program is written in the line comments at the start
+//! of it, where each comment that starts with `expect ` is one of:
+//!
+//! - `expect exit: <code>`, the exit code, which defaults to 0
+//! - `expect stdout: <line>`, the next line of standard output, which is
+//!   otherwise expected to be empty
+//! - `expect stderr: <text>`, text that appears somewhere in standard error
+//!
+//! Any other comment starting with `expect ` is an error, so a misspelled
+//! expectation isn't ignored.
+//!
+//! A program that fails to compile fails the same way in both modes, so this
+//! is also how diagnostics are tested.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const OPT_LEVELS: &[&str] = &["0", "1", "2", "3", "s", "z"];
+
+#[test]
+fn test_examples() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let programs = [root.join("examples"), root.join("tests")]
+        .iter()
+        .flat_map(|dir| programs(dir))
+        .collect::<Vec<_>>();
+    assert!(!programs.is_empty());
+
+    let failures = programs
+        .iter()
+        .flat_map(|program| run(program))
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_expected() {
+    let program = std::env::temp_dir().join(format!("yoke-expected-{}.lir", std::process::id()));
+    fs::write(
+        &program,
+        "-- expect exit: 3\n-- expect stderr: unhandled case\n-- expected to fail\n",
+    )
+    .unwrap();
+    let header = expected(&program).unwrap();
+    assert_eq!(header.exit, 3);
+    assert_eq!(header.stderr, vec!["unhandled case"]);
+
+    fs::write(&program, "-- expect exitt: 3\n").unwrap();
+    assert_eq!(
+        expected(&program).unwrap_err(),
+        "invalid expectation: expect exitt: 3"
+    );
+    fs::remove_file(&program).unwrap();
+}
+
+/// The LIR and MIR files in a directory, in name order.
+fn programs(dir: &Path) -> Vec<PathBuf> {
+    let mut programs = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "lir" || extension == "mir")
+        })
+        .collect::<Vec<_>>();
+    programs.sort();
+    programs
+}
+
+#[derive(Debug, Default)]
+struct Expected {
+    exit: i32,
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+}
+
+fn expected(program: &Path) -> Result<Expected, String> {
+    let text = fs::read_to_string(program).map_err(|e| format!("could not read: {}", e))?;
+
+    let mut expected = Expected::default();
+    let comments = text
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with("--") || line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("--").or(line.strip_prefix('#')))
+        .filter_map(|comment| comment.trim().strip_prefix("expect "));
+    for comment in comments {
+        match comment.split_once(':') {
+            Some((key, code)) if key.trim() == "exit" => {
+                expected.exit = code
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid exit code: expect {}", comment))?
+            }
+            Some((key, text)) if key.trim() == "stdout" => {
+                expected.stdout.push(text.trim().to_string())
+            }
+            Some((key, text)) if key.trim() == "stderr" => {
+                expected.stderr.push(text.trim().to_string())
+            }
+            _ => return Err(format!("invalid expectation: expect {}", comment)),
+        }
+    }
+    Ok(expected)
+}
+
+/// Runs a program every way it can be run, describing every way in which it
+/// didn't do what was expected.
+fn run(program: &Path) -> Vec<String> {
+    let expected = match expected(program) {
+        Ok(expected) => expected,
+        Err(message) => return vec![format!("{}: {}", program.display(), message)],
+    };
+
+    let mut failures = vec![];
+    let interp = yoke(program, "0").arg("--interp").output().unwrap();
+    if let Err(message) = check(&expected, &interp) {
+        failures.push(format!("{} (interp): {}", program.display(), message));
+    }
+
+    for opt_level in OPT_LEVELS {
+        let jit = yoke(program, opt_level).arg("--eval").output().unwrap();
+        if let Err(message) = check(&expected, &jit) {
+            failures.push(format!(
+                "{} (jit, -O{}): {}",
+                program.display(),
+                opt_level,
+                message
+            ));
+        }
+
+        if let Err(message) = check(&expected, &aot(program, opt_level)) {
+            failures.push(format!(
+                "{} (aot, -O{}): {}",
+                program.display(),
+                opt_level,
+                message
+            ));
+        }
+    }
+    failures
+}
+
+fn yoke(program: &Path, opt_level: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_compiler"));
+    command.arg(program).arg(format!("-O{}", opt_level));
+    command
+}
+
+/// Compiles a program to a temporary executable and runs it. What the
+/// compiler prints comes before what the executable prints, and the exit code
+/// is the compiler's if it fails.
+fn aot(program: &Path, opt_level: &str) -> Output {
+    let executable = std::env::temp_dir().join(format!(
+        "yoke-test-{}-{}-O{}",
+        std::process::id(),
+        program.file_name().unwrap().to_string_lossy(),
+        opt_level
+    ));
+    let mut output = yoke(program, opt_level)
+        .arg("-o")
+        .arg(&executable)
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        return output;
+    }
+
+    let run = Command::new(&executable).output().unwrap();
+    let _ = fs::remove_file(&executable);
+    output.status = run.status;
+    output.stdout.extend(run.stdout);
+    output.stderr.extend(run.stderr);
+    output
+}
+
+fn check(expected: &Expected, output: &Output) -> Result<(), String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = strip_colors(&String::from_utf8_lossy(&output.stderr));
+    let describe =
+        |message: String| format!("{}\n--- stdout\n{}--- stderr\n{}", message, stdout, stderr);
+
+    if output.status.code() != Some(expected.exit) {
+        return Err(describe(format!(
+            "expected exit code {}, got {}",
+            expected.exit, output.status
+        )));
+    }
+    if stdout.lines().collect::<Vec<_>>() != expected.stdout {
+        return Err(describe(format!("expected stdout {:?}", expected.stdout)));
+    }
+    if let Some(text) = expected.stderr.iter().find(|text| !stderr.contains(*text)) {
+        return Err(describe(format!("expected {:?} in stderr", text)));
+    }
+    Ok(())
+}
+
+/// Removes the escape sequences that diagnostics are colored with.
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
@@ -1,9 +1,11 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
-use libc::{c_void, calloc, exit, free, malloc, write, STDERR_FILENO};
+use libc::{c_char, c_void, calloc, exit, free, malloc, write, STDERR_FILENO};
 use std::{
+    ffi::CStr,
     mem::size_of,
     ptr::{copy_nonoverlapping, null_mut},
+    slice,
 };
 
 #[repr(C)]
@@ -101,9 +103,46 @@ pub extern "C" fn free_term(term: &mut Term) {
     free_args(term);
 }
 
+/// An entry in the table of constructor names that the compiler puts in every
+/// program.
+#[repr(C)]
+pub struct SymbolName {
+    pub symbol: u32,
+    pub name: *const c_char,
+}
+
+/// Halts the program after reporting the function that reached an unhandled
+/// case and, if it was in a switch, the constructor of the scrutinee.
 #[no_mangle]
-pub extern "C" fn todo() {
-    let message = c"unhandled case\n";
+pub extern "C" fn todo(
+    fun: *const c_char,
+    scrutinee: *const Term,
+    names: *const SymbolName,
+    count: usize,
+) {
+    print(c"unhandled case in ");
+    print(unsafe { CStr::from_ptr(fun) });
+
+    if let Some(scrutinee) = unsafe { scrutinee.as_ref() } {
+        let names = unsafe { slice::from_raw_parts(names, count) };
+        if let Some(name) = symbol_name(scrutinee.symbol, names) {
+            print(c": ");
+            print(name);
+        }
+    }
+
+    print(c"\n");
+    unsafe { exit(3) };
+}
+
+fn symbol_name(symbol: u32, names: &[SymbolName]) -> Option<&CStr> {
+    names
+        .iter()
+        .find(|name| name.symbol == symbol)
+        .map(|name| unsafe { CStr::from_ptr(name.name) })
+}
+
+fn print(message: &CStr) {
     unsafe {
         write(
             STDERR_FILENO,
@@ -111,7 +150,6 @@ pub extern "C" fn todo() {
             message.count_bytes(),
         );
     }
-    unsafe { exit(3) };
 }
 
 fn alloc_terms(capacity: usize) -> *mut Term {
@@ -390,4 +428,21 @@ mod test {
 
         free_term(&mut term1);
     }
+
+    #[test]
+    fn test_symbol_name() {
+        let names = [
+            SymbolName {
+                symbol: 1,
+                name: c"True".as_ptr(),
+            },
+            SymbolName {
+                symbol: 2,
+                name: c"False".as_ptr(),
+            },
+        ];
+
+        assert_eq!(symbol_name(2, &names), Some(c"False"));
+        assert_eq!(symbol_name(3, &names), None);
+    }
 }